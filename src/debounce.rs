@@ -0,0 +1,52 @@
+//! How long the playground waits after an input edit before re-parsing.
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MAX_DELAY_MS: u32 = 800;
+
+/// Parses faster than this run on every keystroke.
+const IMMEDIATE_PARSE_MS: f64 = 1.0;
+
+/// Scales the delay with the previous parse's duration, so cheap parses feel
+/// live and expensive ones wait until typing pauses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Debounce {
+    pub max_delay_ms: u32,
+}
+
+impl Default for Debounce {
+    fn default() -> Debounce {
+        Debounce {
+            max_delay_ms: DEFAULT_MAX_DELAY_MS,
+        }
+    }
+}
+
+impl Debounce {
+    /// Returns the delay before the next parse, given how long the last one
+    /// took, or `None` if nothing was parsed yet. Zero means parse now.
+    pub fn delay(&self, last_parse_ms: Option<f64>) -> u32 {
+        match last_parse_ms {
+            Some(ms) if ms < IMMEDIATE_PARSE_MS => 0,
+            Some(ms) => ((ms * 2.0).ceil() as u32).min(self.max_delay_ms),
+            None => self.max_delay_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_follow_parse_times() {
+        let debounce = Debounce { max_delay_ms: 500 };
+
+        assert_eq!(debounce.delay(None), 500);
+        assert_eq!(debounce.delay(Some(0.3)), 0);
+        assert_eq!(debounce.delay(Some(1.2)), 3);
+        assert_eq!(debounce.delay(Some(40.0)), 80);
+        assert_eq!(debounce.delay(Some(2000.0)), 500);
+    }
+}
@@ -0,0 +1,265 @@
+use pest::error::{Error, ErrorVariant, InputLocation};
+use pest_meta::parser::{self, Rule};
+use serde::{Deserialize, Serialize};
+
+use crate::position::{LineIndex, Position};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+}
+
+/// Identifies the stage that rejected the grammar. The serialized names are
+/// part of the `lint` and `format` API and must not change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticCode {
+    /// The grammar does not parse as pest syntax.
+    Syntax,
+    /// Rule names are undefined, duplicated or reserved.
+    Validation,
+    /// Rules are left-recursive, repeat empty expressions and the like.
+    Semantic,
+    /// The formatter could not process an otherwise valid grammar.
+    Format,
+    /// The options passed along with the grammar are invalid.
+    Options,
+}
+
+/// A grammar diagnostic as returned to the editor by `lint`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub from: Position,
+    pub to: Position,
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+}
+
+/// A parse failure of the playground input, broken down into the pieces the
+/// input pane needs to point at the failing spot.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputDiagnostic {
+    /// Byte span of the failure; `start == end` for position errors.
+    pub start: usize,
+    pub end: usize,
+    /// Editor positions of `start` and `end`.
+    pub from: Position,
+    pub to: Position,
+    /// Rules that were expected at the failing position.
+    pub expected: Vec<String>,
+    /// Rules that matched at the failing position but should not have.
+    pub unexpected: Vec<String>,
+    /// Free-form message for custom errors.
+    pub message: Option<String>,
+    /// The input line containing the failure, without its line ending.
+    pub line_text: String,
+    /// Byte offset at which `line_text` starts in the input.
+    pub line_start: usize,
+}
+
+impl InputDiagnostic {
+    pub fn from_error(error: Error<&str>, input: &str) -> InputDiagnostic {
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span((start, end)) => (start, end),
+        };
+        let lines = LineIndex::new(input);
+        let from = lines.position(start);
+        let line = lines.line_range(from.line).unwrap_or_default();
+
+        let (expected, unexpected, message) = match error.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => (
+                positives.into_iter().map(str::to_owned).collect(),
+                negatives.into_iter().map(str::to_owned).collect(),
+                None,
+            ),
+            ErrorVariant::CustomError { message } => (vec![], vec![], Some(message)),
+        };
+
+        InputDiagnostic {
+            start,
+            end,
+            from,
+            to: lines.position(end),
+            expected,
+            unexpected,
+            message,
+            line_text: input[line.clone()].to_owned(),
+            line_start: line.start,
+        }
+    }
+
+    /// One-line summary, e.g. `expected one of a, b; unexpected c`.
+    pub fn summary(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+
+        describe_attempts(&self.expected, &self.unexpected)
+    }
+
+    /// The failing line followed by a marker line underlining the failure.
+    pub fn underline(&self) -> String {
+        let from = (self.start - self.line_start).min(self.line_text.len());
+        let to = (self.end - self.line_start).clamp(from, self.line_text.len());
+
+        let prefix_len = self.line_text[..from].chars().count();
+        let span_len = self.line_text[from..to].chars().count().max(1);
+
+        format!(
+            "{}\n{}{}",
+            self.line_text,
+            " ".repeat(prefix_len),
+            "^".repeat(span_len)
+        )
+    }
+
+    /// Multi-line report shown in the output pane.
+    pub fn report(&self) -> String {
+        format!(
+            " --> {}:{}\n{}\n{}",
+            self.from.line + 1,
+            self.from.ch + 1,
+            self.underline(),
+            self.summary()
+        )
+    }
+}
+
+/// Renders pest's positive and negative attempts as readable text, e.g.
+/// `expected one of a, b; unexpected c`.
+pub fn describe_attempts(expected: &[String], unexpected: &[String]) -> String {
+    let mut parts = vec![];
+    match expected {
+        [] => {}
+        [rule] => parts.push(format!("expected {}", rule)),
+        rules => parts.push(format!("expected one of {}", rules.join(", "))),
+    }
+    match unexpected {
+        [] => {}
+        [rule] => parts.push(format!("unexpected {}", rule)),
+        rules => parts.push(format!("unexpected one of {}", rules.join(", "))),
+    }
+
+    if parts.is_empty() {
+        "unknown parsing error".to_owned()
+    } else {
+        parts.join("; ")
+    }
+}
+
+pub fn convert_error(error: Error<Rule>, code: DiagnosticCode, lines: &LineIndex) -> Diagnostic {
+    let message = match error.variant {
+        ErrorVariant::ParsingError {
+            positives,
+            negatives,
+        } => {
+            let rename = |rules: Vec<Rule>| -> Vec<String> {
+                rules.iter().map(parser::rename_meta_rule).collect()
+            };
+
+            describe_attempts(&rename(positives), &rename(negatives))
+        }
+        ErrorVariant::CustomError { message } => message,
+    };
+
+    let (start, end) = match error.location {
+        InputLocation::Pos(pos) => (pos, pos),
+        InputLocation::Span((start, end)) => (start, end),
+    };
+
+    Diagnostic {
+        from: lines.position(start),
+        to: lines.position(end),
+        severity: Severity::Error,
+        code,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_error_handles_parsing_errors() {
+        let error = Error::new_from_pos(
+            ErrorVariant::ParsingError {
+                positives: vec![Rule::opening_brace, Rule::assignment_operator],
+                negatives: vec![Rule::closing_brace],
+            },
+            pest::Position::new("a = }", 4).unwrap(),
+        );
+
+        let diagnostic = convert_error(error, DiagnosticCode::Syntax, &LineIndex::new("a = }"));
+
+        assert_eq!(diagnostic.from, Position { line: 0, ch: 4 });
+        assert_eq!(diagnostic.to, diagnostic.from);
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(
+            diagnostic.message,
+            "expected one of `{`, `=`; unexpected `}`"
+        );
+    }
+
+    fn input_error(input: &str, start: usize, end: usize) -> InputDiagnostic {
+        let error = Error::new_from_span(
+            ErrorVariant::ParsingError {
+                positives: vec!["digit"],
+                negatives: vec![],
+            },
+            pest::Span::new(input, start, end).unwrap(),
+        );
+
+        InputDiagnostic::from_error(error, input)
+    }
+
+    #[test]
+    fn input_errors_underline_the_span() {
+        let diagnostic = input_error("ab\r\nc\u{e9}de", 7, 9);
+
+        assert_eq!(diagnostic.line_text, "c\u{e9}de");
+        assert_eq!(diagnostic.line_start, 4);
+        assert_eq!(diagnostic.underline(), "c\u{e9}de\n  ^^");
+        assert_eq!(
+            diagnostic.report(),
+            " --> 2:3\nc\u{e9}de\n  ^^\nexpected digit"
+        );
+    }
+
+    #[test]
+    fn input_errors_split_lines_on_lone_carriage_returns() {
+        let diagnostic = input_error("ab\rcd", 4, 4);
+
+        assert_eq!(diagnostic.from, Position { line: 1, ch: 1 });
+        assert_eq!(diagnostic.line_text, "cd");
+        assert_eq!(diagnostic.line_start, 3);
+        assert_eq!(diagnostic.underline(), "cd\n ^");
+    }
+
+    #[test]
+    fn span_errors_map_both_ends() {
+        let error = Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: "rule a is undefined".to_owned(),
+            },
+            pest::Span::new("x\ny = { a }", 8, 9).unwrap(),
+        );
+
+        let diagnostic = convert_error(
+            error,
+            DiagnosticCode::Validation,
+            &LineIndex::new("x\ny = { a }"),
+        );
+
+        assert_eq!(diagnostic.from, Position { line: 1, ch: 6 });
+        assert_eq!(diagnostic.to, Position { line: 1, ch: 7 });
+        assert_eq!(diagnostic.code, DiagnosticCode::Validation);
+    }
+}
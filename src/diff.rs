@@ -0,0 +1,179 @@
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// One line of a line-level diff.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+/// Computes a line-level diff turning `old` into `new`, using a longest
+/// common subsequence of the lines between their common prefix and suffix.
+///
+/// The subsequence is found with Hirschberg's algorithm, so the diff takes
+/// space linear in the number of lines.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut diff: Vec<_> = old[..prefix]
+        .iter()
+        .map(|text| line(DiffKind::Equal, text))
+        .collect();
+
+    diff_middle(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+        &mut diff,
+    );
+
+    diff.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|text| line(DiffKind::Equal, text)),
+    );
+
+    diff
+}
+
+fn line(kind: DiffKind, text: &str) -> DiffLine {
+    DiffLine {
+        kind,
+        text: text.to_owned(),
+    }
+}
+
+/// Appends the diff of `old` and `new` to `diff`, splitting `old` in half
+/// and `new` where the halves' common subsequences meet.
+fn diff_middle(old: &[&str], new: &[&str], diff: &mut Vec<DiffLine>) {
+    match old {
+        [] => {
+            diff.extend(new.iter().map(|text| line(DiffKind::Insert, text)));
+        }
+        [only] => match new.iter().position(|text| text == only) {
+            Some(found) => {
+                diff.extend(new[..found].iter().map(|text| line(DiffKind::Insert, text)));
+                diff.push(line(DiffKind::Equal, only));
+                diff.extend(
+                    new[found + 1..]
+                        .iter()
+                        .map(|text| line(DiffKind::Insert, text)),
+                );
+            }
+            None => {
+                diff.push(line(DiffKind::Delete, only));
+                diff.extend(new.iter().map(|text| line(DiffKind::Insert, text)));
+            }
+        },
+        _ if new.is_empty() => {
+            diff.extend(old.iter().map(|text| line(DiffKind::Delete, text)));
+        }
+        _ => {
+            let (top, bottom) = old.split_at(old.len() / 2);
+            let forward = lcs_lengths(top.iter(), new.iter());
+            let mut backward = lcs_lengths(bottom.iter().rev(), new.iter().rev());
+            backward.reverse();
+
+            // The first best split keeps deletions ahead of insertions.
+            let split = (0..=new.len())
+                .rev()
+                .max_by_key(|&k| forward[k] + backward[k])
+                .unwrap_or(0);
+
+            diff_middle(top, &new[..split], diff);
+            diff_middle(bottom, &new[split..], diff);
+        }
+    }
+}
+
+/// The LCS length of all of `old` with each prefix of `new`, in one row.
+fn lcs_lengths<'a>(
+    old: impl Iterator<Item = &'a &'a str>,
+    new: impl Iterator<Item = &'a &'a str> + Clone,
+) -> Vec<usize> {
+    let mut row = vec![0; new.clone().count() + 1];
+
+    for a in old {
+        let mut diagonal = 0;
+        for (j, b) in new.clone().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if a == b {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(diff: &[DiffLine]) -> String {
+        diff.iter()
+            .map(|line| match line.kind {
+                DiffKind::Equal => format!(" {}\n", line.text),
+                DiffKind::Delete => format!("-{}\n", line.text),
+                DiffKind::Insert => format!("+{}\n", line.text),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_texts_are_all_equal() {
+        let diff = diff_lines("a\nb\n", "a\nb\n");
+
+        assert!(diff.iter().all(|line| line.kind == DiffKind::Equal));
+        assert_eq!(diff.len(), 2);
+    }
+
+    #[test]
+    fn changed_lines_are_replaced() {
+        let diff = diff_lines("a\nb={x}\nc\nd\n", "a\nb = { x }\nc\nnew\nd\n");
+
+        assert_eq!(render(&diff), " a\n-b={x}\n+b = { x }\n c\n+new\n d\n");
+    }
+
+    #[test]
+    fn removed_lines_are_deleted() {
+        assert_eq!(render(&diff_lines("a\n\n\nb", "a\n\nb")), " a\n \n-\n b\n");
+    }
+
+    #[test]
+    fn long_texts_diff_every_line() {
+        let old: String = (0..5000).map(|i| format!("{}\n", i)).collect();
+        let new: String = (0..5000)
+            .map(|i| match i % 3 {
+                0 => format!("{}\n", i),
+                1 => format!("changed {}\n", i),
+                _ => String::new(),
+            })
+            .collect();
+        let diff = diff_lines(&old, &new);
+
+        let count = |kind| diff.iter().filter(|line| line.kind == kind).count();
+        assert_eq!(count(DiffKind::Equal), 1667);
+        assert_eq!(count(DiffKind::Delete), 3333);
+        assert_eq!(count(DiffKind::Insert), 1667);
+    }
+}
@@ -0,0 +1,307 @@
+//! S-expression, XML and YAML renderings of parse results, for comparing
+//! trees with other tools.
+
+use serde::{Deserialize, Serialize};
+
+use crate::json::write_string;
+use crate::position::LineIndex;
+use crate::tree::ParseTree;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Like tree-sitter's `parse` output: nested rules without text, with
+    /// node tags as field names.
+    SExpr,
+    Xml,
+    Yaml,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExportOptions {
+    /// Add the span of every pair: `[line, ch] - [line, ch]` positions in
+    /// S-expressions and byte offsets in XML and YAML.
+    pub spans: bool,
+}
+
+/// Renders every top-level node of `tree`, parsed from `input`, in `format`.
+pub fn export_tree(
+    tree: &ParseTree,
+    input: &str,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> String {
+    let exporter = Exporter {
+        tree,
+        input,
+        lines: LineIndex::new(input),
+        options,
+        out: String::new(),
+    };
+    let roots: Vec<_> = tree.roots().collect();
+
+    match format {
+        ExportFormat::SExpr => exporter.sexpr(&roots),
+        ExportFormat::Xml => exporter.xml(&roots),
+        ExportFormat::Yaml => exporter.yaml(&roots),
+    }
+}
+
+struct Exporter<'a> {
+    tree: &'a ParseTree,
+    input: &'a str,
+    lines: LineIndex<'a>,
+    options: &'a ExportOptions,
+    out: String,
+}
+
+impl Exporter<'_> {
+    fn sexpr(mut self, roots: &[usize]) -> String {
+        for (i, &root) in roots.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            self.sexpr_node(root, 0);
+        }
+        self.out
+    }
+
+    fn sexpr_node(&mut self, index: usize, depth: usize) {
+        let node = &self.tree.nodes[index];
+
+        if let Some(tag) = &node.tag {
+            self.out.push_str(&format!("{}: ", tag));
+        }
+        self.out.push('(');
+        self.out.push_str(&node.rule);
+        if self.options.spans {
+            let from = self.lines.position(node.start);
+            let to = self.lines.position(node.end);
+            self.out.push_str(&format!(
+                " [{}, {}] - [{}, {}]",
+                from.line, from.ch, to.line, to.ch
+            ));
+        }
+
+        for &child in &node.children {
+            self.out.push('\n');
+            self.out.push_str(&"  ".repeat(depth + 1));
+            self.sexpr_node(child, depth + 1);
+        }
+        self.out.push(')');
+    }
+
+    fn xml(mut self, roots: &[usize]) -> String {
+        self.out
+            .push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<pairs>\n");
+        for &root in roots {
+            self.xml_node(root, 1);
+        }
+        self.out.push_str("</pairs>");
+        self.out
+    }
+
+    fn xml_node(&mut self, index: usize, depth: usize) {
+        let node = &self.tree.nodes[index];
+        let indent = "  ".repeat(depth);
+
+        self.out.push_str(&format!("{}<{}", indent, node.rule));
+        if let Some(tag) = &node.tag {
+            self.out.push_str(&format!(" tag=\"{}\"", escape_xml(tag)));
+        }
+        if self.options.spans {
+            self.out
+                .push_str(&format!(" start=\"{}\" end=\"{}\"", node.start, node.end));
+        }
+
+        if node.children.is_empty() {
+            let text = escape_xml(&self.input[node.start..node.end]);
+            self.out.push_str(&format!(">{}</{}>\n", text, node.rule));
+        } else {
+            self.out.push_str(">\n");
+            for &child in &node.children {
+                self.xml_node(child, depth + 1);
+            }
+            self.out.push_str(&format!("{}</{}>\n", indent, node.rule));
+        }
+    }
+
+    fn yaml(mut self, roots: &[usize]) -> String {
+        if roots.is_empty() {
+            return String::from("[]");
+        }
+
+        self.yaml_list(roots, 0);
+        self.out.pop();
+        self.out
+    }
+
+    /// Writes `indices` as a block sequence of mappings. Strings are written
+    /// as double-quoted scalars, whose escapes match JSON's.
+    fn yaml_list(&mut self, indices: &[usize], depth: usize) {
+        let indent = "  ".repeat(depth);
+
+        for &index in indices {
+            let node = &self.tree.nodes[index];
+
+            self.out.push_str(&format!("{}- rule: ", indent));
+            write_string(&mut self.out, &node.rule);
+            self.out.push('\n');
+            if let Some(tag) = &node.tag {
+                self.out.push_str(&format!("{}  tag: ", indent));
+                write_string(&mut self.out, tag);
+                self.out.push('\n');
+            }
+            if self.options.spans {
+                self.out.push_str(&format!(
+                    "{}  pos: [{}, {}]\n",
+                    indent, node.start, node.end
+                ));
+            }
+
+            if node.children.is_empty() {
+                self.out.push_str(&format!("{}  text: ", indent));
+                write_string(&mut self.out, &self.input[node.start..node.end]);
+                self.out.push('\n');
+            } else {
+                self.out.push_str(&format!("{}  inner:\n", indent));
+                self.yaml_list(&node.children, depth + 2);
+            }
+        }
+    }
+}
+
+/// Escapes the markup chars of `text`, and its quotes for attribute values.
+///
+/// XML 1.0 can't represent control chars other than tabs and line breaks, so
+/// those are replaced by U+FFFD.
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' => escaped.push(c),
+            '\r' => escaped.push_str("&#13;"),
+            c if c < ' ' => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::fixture::{pair_list, pair_tree};
+
+    use super::*;
+
+    const SPANS: ExportOptions = ExportOptions { spans: true };
+
+    #[test]
+    fn sexprs_look_like_tree_sitter() {
+        let input = "ab=<1>";
+        let tree = pair_tree(r#""<" ~ ASCII_DIGIT ~ ">""#, input);
+
+        assert_eq!(
+            export_tree(&tree, input, ExportFormat::SExpr, &ExportOptions::default()),
+            "(pair\n  (key\n    name: (ident))\n  (value))"
+        );
+        assert_eq!(
+            export_tree(&tree, input, ExportFormat::SExpr, &SPANS),
+            "(pair [0, 0] - [0, 6]\n  (key [0, 0] - [0, 2]\n    name: (ident [0, 0] - [0, 2]))\n  (value [0, 3] - [0, 6]))"
+        );
+    }
+
+    #[test]
+    fn xml_escapes_text() {
+        let input = "ab=<1>";
+
+        assert_eq!(
+            export_tree(
+                &pair_tree(r#""<" ~ ASCII_DIGIT ~ ">""#, input),
+                input,
+                ExportFormat::Xml,
+                &SPANS
+            ),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<pairs>\n  <pair start=\"0\" end=\"6\">\n    <key start=\"0\" end=\"2\">\n      <ident tag=\"name\" start=\"0\" end=\"2\">ab</ident>\n    </key>\n    <value start=\"3\" end=\"6\">&lt;1&gt;</value>\n  </pair>\n</pairs>"
+        );
+    }
+
+    #[test]
+    fn yaml_nests_sequences() {
+        let input = "ab=<1>";
+
+        assert_eq!(
+            export_tree(
+                &pair_tree(r#""<" ~ ASCII_DIGIT ~ ">""#, input),
+                input,
+                ExportFormat::Yaml,
+                &ExportOptions::default()
+            ),
+            "- rule: \"pair\"\n  inner:\n    - rule: \"key\"\n      inner:\n        - rule: \"ident\"\n          tag: \"name\"\n          text: \"ab\"\n    - rule: \"value\"\n      text: \"<1>\""
+        );
+    }
+
+    #[test]
+    fn special_chars_are_escaped_per_format() {
+        let input = "ab=\"\\\n\t<&>'";
+        let tree = pair_tree("ANY+", input);
+
+        assert!(export_tree(&tree, input, ExportFormat::Xml, &SPANS)
+            .contains("<value start=\"3\" end=\"11\">&quot;\\\n\t&lt;&amp;&gt;'</value>"));
+        assert!(
+            export_tree(&tree, input, ExportFormat::Yaml, &SPANS).ends_with(
+                "- rule: \"value\"\n      pos: [3, 11]\n      text: \"\\\"\\\\\\n\\t<&>'\""
+            )
+        );
+        assert!(export_tree(&tree, input, ExportFormat::SExpr, &SPANS)
+            .ends_with("(value [0, 3] - [1, 5]))"));
+    }
+
+    #[test]
+    fn non_ascii_text_is_kept_and_columns_count_utf16_units() {
+        let input = "ab=é😀ñ";
+        let tree = pair_tree("ANY+", input);
+
+        assert!(export_tree(&tree, input, ExportFormat::SExpr, &SPANS)
+            .ends_with("(value [0, 3] - [0, 7]))"));
+        assert!(export_tree(&tree, input, ExportFormat::Xml, &SPANS)
+            .contains("<value start=\"3\" end=\"11\">é😀ñ</value>"));
+        assert!(export_tree(&tree, input, ExportFormat::Yaml, &SPANS).ends_with("text: \"é😀ñ\""));
+    }
+
+    #[test]
+    fn every_root_is_exported() {
+        let input = "ab=1;cd=23";
+        let tree = pair_list("ASCII_DIGIT+", input);
+
+        assert_eq!(
+            export_tree(&tree, input, ExportFormat::SExpr, &ExportOptions::default()),
+            "(pair\n  (key\n    name: (ident))\n  (value))\n(pair\n  (key\n    name: (ident))\n  (value))"
+        );
+        let xml = export_tree(&tree, input, ExportFormat::Xml, &SPANS);
+        assert!(xml.contains("</pair>\n  <pair start=\"5\" end=\"10\">\n"));
+        let yaml = export_tree(&tree, input, ExportFormat::Yaml, &ExportOptions::default());
+        assert_eq!(yaml.matches("\n- rule: \"pair\"").count(), 1);
+        assert!(yaml.starts_with("- rule: \"pair\""));
+    }
+
+    #[test]
+    fn empty_trees_export_empty_documents() {
+        let export =
+            |format| export_tree(&ParseTree::default(), "", format, &ExportOptions::default());
+
+        assert_eq!(export(ExportFormat::SExpr), "");
+        assert_eq!(
+            export(ExportFormat::Xml),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<pairs>\n</pairs>"
+        );
+        assert_eq!(export(ExportFormat::Yaml), "[]");
+    }
+}
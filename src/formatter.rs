@@ -0,0 +1,792 @@
+use pest::iterators::Pair;
+use pest_fmt::{Formatter, PestError};
+use pest_meta::optimizer;
+use pest_meta::parser::{self, Rule};
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostic::{convert_error, Diagnostic, DiagnosticCode, Severity};
+use crate::diff::{diff_lines, DiffLine};
+use crate::grammar;
+use crate::position::{LineIndex, Position};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentPolicy {
+    Keep,
+    /// Drops `//` and `/* */` comments but keeps `///` and `//!` docs.
+    Strip,
+}
+
+/// Layout settings applied on top of pest_fmt. The defaults reproduce
+/// pest_fmt's own output.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormatOptions {
+    /// Spaces per indentation level inside multi-line rules, at least
+    /// `MIN_INDENT_WIDTH`.
+    pub indent_width: usize,
+    /// Single-line rules wider than this are broken up at their top-level
+    /// choices. `None` never wraps.
+    pub choice_wrap_width: Option<usize>,
+    /// Pads the names of adjacent rules so that their `=` signs line up.
+    pub align_assignments: bool,
+    /// Exact number of blank lines between rules. `None` keeps the grouping
+    /// of the original grammar.
+    pub blank_lines_between_rules: Option<usize>,
+    pub comments: CommentPolicy,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            indent_width: PEST_FMT_INDENT,
+            choice_wrap_width: None,
+            align_assignments: true,
+            blank_lines_between_rules: None,
+            comments: CommentPolicy::Keep,
+        }
+    }
+}
+
+const PEST_FMT_INDENT: usize = 4;
+
+/// The narrowest indent that `| ` can hang to the left of choices in.
+/// Narrower ones would be laid out differently when formatted again.
+pub const MIN_INDENT_WIDTH: usize = 2;
+
+/// Outcome of formatting a grammar, as returned to the editor by `format`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "status",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum FormatResult {
+    Ok {
+        formatted: String,
+        /// Line-level changes from the original grammar.
+        diff: Vec<DiffLine>,
+        /// Whether formatting `formatted` again leaves it unchanged.
+        idempotent: bool,
+        /// Whether `formatted` compiles to the same optimized rules as the
+        /// original, or `None` if the original does not compile.
+        equivalent: Option<bool>,
+    },
+    Error {
+        errors: Vec<Diagnostic>,
+    },
+}
+
+/// Formats `grammar` and checks the result, refusing to touch it if it does
+/// not parse.
+pub fn format_grammar(grammar: &str, options: &FormatOptions) -> FormatResult {
+    let formatted = match format_text(grammar, options) {
+        Ok(formatted) => formatted,
+        Err(errors) => return FormatResult::Error { errors },
+    };
+
+    let idempotent = format_text(&formatted, options).is_ok_and(|again| again == formatted);
+    let equivalent = grammar::check(grammar).ok().map(|ast| {
+        grammar::check(&formatted)
+            .is_ok_and(|formatted| optimizer::optimize(formatted) == optimizer::optimize(ast))
+    });
+
+    FormatResult::Ok {
+        diff: diff_lines(grammar, &formatted),
+        formatted,
+        idempotent,
+        equivalent,
+    }
+}
+
+fn format_text(grammar: &str, options: &FormatOptions) -> Result<String, Vec<Diagnostic>> {
+    if options.indent_width < MIN_INDENT_WIDTH {
+        let start = Position { line: 0, ch: 0 };
+
+        return Err(vec![Diagnostic {
+            from: start,
+            to: start,
+            severity: Severity::Error,
+            code: DiagnosticCode::Options,
+            message: format!("indent width must be at least {}", MIN_INDENT_WIDTH),
+        }]);
+    }
+    if let Err(error) = parser::parse(Rule::grammar_rules, grammar) {
+        let lines = LineIndex::new(grammar);
+
+        return Err(vec![convert_error(error, DiagnosticCode::Syntax, &lines)]);
+    }
+
+    match Formatter::new(grammar).format() {
+        Ok(formatted) => Ok(restyle(&formatted, options)),
+        Err(error) => Err(vec![formatter_error(error)]),
+    }
+}
+
+/// The part of a grammar to reformat with `format_range`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum FormatTarget {
+    /// Every rule definition overlapping the byte range `start..end`.
+    Range { start: usize, end: usize },
+    /// The definition of the named rule.
+    Rule { rule: String },
+}
+
+/// A replacement of the text between `from` and `to`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TextEdit {
+    pub from: Position,
+    pub to: Position,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum RangeFormatResult {
+    /// `edit` is `None` if no rule was targeted or it is already formatted.
+    Ok {
+        edit: Option<TextEdit>,
+    },
+    Error {
+        errors: Vec<Diagnostic>,
+    },
+}
+
+/// Reformats only the rule definitions picked by `target`, leaving the rest
+/// of `grammar` untouched.
+///
+/// The whole grammar is formatted so that alignment and spacing match what
+/// `format_grammar` would produce, and only the picked rules' lines are
+/// spliced back.
+pub fn format_range(
+    grammar: &str,
+    target: &FormatTarget,
+    options: &FormatOptions,
+) -> RangeFormatResult {
+    let formatted = match format_text(grammar, options) {
+        Ok(formatted) => formatted,
+        Err(errors) => return RangeFormatResult::Error { errors },
+    };
+
+    let original = rule_spans(grammar);
+    let picked: Vec<_> = original
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, start, end))| match target {
+            FormatTarget::Range {
+                start: from,
+                end: to,
+            } => start <= to && from <= end,
+            FormatTarget::Rule { rule } => name == rule,
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    let (first, last) = match (picked.first(), picked.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return RangeFormatResult::Ok { edit: None },
+    };
+
+    let layout = rule_spans(&formatted);
+    let same_rules = layout.len() == original.len()
+        && layout
+            .iter()
+            .zip(&original)
+            .all(|((a, _, _), (b, _, _))| a == b);
+    if !same_rules {
+        return RangeFormatResult::Error {
+            errors: vec![formatter_error(PestError::FormatFail(String::from(
+                "the formatted grammar has different rules",
+            )))],
+        };
+    }
+
+    let (start, end) = (original[first].1, original[last].2);
+    let text = &formatted[layout[first].1..layout[last].2];
+    let lines = LineIndex::new(grammar);
+
+    RangeFormatResult::Ok {
+        edit: (text != &grammar[start..end]).then(|| TextEdit {
+            from: lines.position(start),
+            to: lines.position(end),
+            text: text.to_owned(),
+        }),
+    }
+}
+
+/// The name and byte range of every rule definition in a grammar that
+/// parses.
+fn rule_spans(grammar: &str) -> Vec<(&str, usize, usize)> {
+    let Ok(pairs) = parser::parse(Rule::grammar_rules, grammar) else {
+        return vec![];
+    };
+
+    pairs
+        .filter(|pair| pair.as_rule() == Rule::grammar_rule)
+        .filter_map(|pair| {
+            let span = pair.as_span();
+            let name = pair.into_inner().next()?;
+
+            (name.as_rule() == Rule::identifier).then(|| (name.as_str(), span.start(), span.end()))
+        })
+        .collect()
+}
+
+/// pest_fmt does not report locations, so its own failures are pinned to the
+/// start of the grammar.
+fn formatter_error(error: PestError) -> Diagnostic {
+    let message = match error {
+        PestError::IOError(message)
+        | PestError::Unreachable(message)
+        | PestError::ParseFail(message)
+        | PestError::FormatFail(message) => message,
+    };
+    let start = Position { line: 0, ch: 0 };
+
+    Diagnostic {
+        from: start,
+        to: start,
+        severity: Severity::Error,
+        code: DiagnosticCode::Format,
+        message,
+    }
+}
+
+enum Item<'a> {
+    Blank,
+    Line(&'a str),
+    Rule(RuleLayout<'a>),
+}
+
+struct RuleLayout<'a> {
+    name: &'a str,
+    modifier: &'a str,
+    /// Everything between the braces, as laid out by pest_fmt.
+    body: &'a str,
+    /// Top-level alternatives, if the rule is a choice.
+    choices: Vec<&'a str>,
+}
+
+/// Re-lays out pest_fmt's output according to `options`.
+fn restyle(formatted: &str, options: &FormatOptions) -> String {
+    let mut items = match split_items(formatted) {
+        Some(items) => items,
+        None => return formatted.to_owned(),
+    };
+
+    if options.comments == CommentPolicy::Strip {
+        items = strip_comments(items);
+    }
+    if let Some(blank_lines) = options.blank_lines_between_rules {
+        items = space_rules(items, blank_lines);
+    }
+
+    let mut output = String::new();
+    let mut group_start = 0;
+
+    while group_start < items.len() {
+        let group_end = items[group_start..]
+            .iter()
+            .position(|item| matches!(item, Item::Blank))
+            .map_or(items.len(), |i| group_start + i);
+        let name_width = items[group_start..group_end]
+            .iter()
+            .filter_map(|item| match item {
+                Item::Rule(rule) if options.align_assignments => Some(rule.name.chars().count()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        for item in &items[group_start..group_end] {
+            match item {
+                Item::Blank => {}
+                Item::Line(line) => output.push_str(line),
+                Item::Rule(rule) => render_rule(&mut output, rule, name_width, options),
+            }
+            output.push('\n');
+        }
+
+        if group_end < items.len() {
+            output.push('\n');
+        }
+        group_start = group_end + 1;
+    }
+
+    output
+}
+
+/// Splits pest_fmt's output into rules and the lines between them, or
+/// returns `None` if it cannot be parsed back.
+fn split_items(formatted: &str) -> Option<Vec<Item<'_>>> {
+    let pairs = parser::parse(Rule::grammar_rules, formatted).ok()?;
+    let mut rules: Vec<(usize, usize, RuleLayout)> = pairs
+        .filter(|pair| pair.as_rule() == Rule::grammar_rule)
+        .filter_map(|pair| {
+            let span = pair.as_span();
+            rule_layout(formatted, pair).map(|rule| (span.start(), span.end(), rule))
+        })
+        .collect();
+    rules.reverse();
+
+    let mut items = vec![];
+    let mut offset = 0;
+    let mut lines = formatted.split_inclusive('\n');
+
+    while let Some(line) = lines.next() {
+        let start = offset;
+        offset += line.len();
+
+        if rules
+            .last()
+            .is_some_and(|&(rule_start, _, _)| rule_start == start)
+        {
+            let (_, end, rule) = rules.pop().unwrap();
+            while offset < end {
+                offset += lines.next()?.len();
+            }
+            items.push(Item::Rule(rule));
+        } else if line.trim().is_empty() {
+            items.push(Item::Blank);
+        } else {
+            items.push(Item::Line(line.trim_end()));
+        }
+    }
+
+    rules.is_empty().then_some(items)
+}
+
+fn rule_layout<'a>(formatted: &'a str, pair: Pair<'a, Rule>) -> Option<RuleLayout<'a>> {
+    let mut name = "";
+    let mut modifier = "";
+    let mut body_start = 0;
+    let mut choices = vec![];
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::line_doc => return None,
+            Rule::identifier => name = inner.as_str(),
+            Rule::silent_modifier
+            | Rule::atomic_modifier
+            | Rule::compound_atomic_modifier
+            | Rule::non_atomic_modifier => modifier = inner.as_str(),
+            Rule::opening_brace => body_start = inner.as_span().end(),
+            Rule::expression => choices = top_level_choices(formatted, inner),
+            Rule::closing_brace => {
+                return Some(RuleLayout {
+                    name,
+                    modifier,
+                    body: &formatted[body_start..inner.as_span().start()],
+                    choices,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn top_level_choices<'a>(formatted: &'a str, expression: Pair<'a, Rule>) -> Vec<&'a str> {
+    let span = expression.as_span();
+    let mut choices = vec![];
+    let mut start = span.start();
+
+    for inner in expression.into_inner() {
+        if inner.as_rule() == Rule::choice_operator {
+            choices.push(formatted[start..inner.as_span().start()].trim());
+            start = inner.as_span().end();
+        }
+    }
+    choices.push(formatted[start..span.end()].trim());
+    choices.retain(|choice| !choice.is_empty());
+
+    if choices.len() > 1 {
+        choices
+    } else {
+        vec![]
+    }
+}
+
+fn strip_comments(items: Vec<Item<'_>>) -> Vec<Item<'_>> {
+    let mut in_block = false;
+
+    items
+        .into_iter()
+        .filter(|item| match item {
+            Item::Line(line) => !is_comment_line(line, &mut in_block),
+            _ => true,
+        })
+        .collect()
+}
+
+fn is_comment_line(line: &str, in_block: &mut bool) -> bool {
+    let line = line.trim();
+
+    if *in_block {
+        *in_block = !line.ends_with("*/");
+        return true;
+    }
+    if line.starts_with("/*") {
+        *in_block = !line.ends_with("*/") || line.len() < 4;
+        return true;
+    }
+
+    line.starts_with("//") && !line.starts_with("///") && !line.starts_with("//!")
+}
+
+/// Puts exactly `blank_lines` blank lines before every rule, keeping the
+/// comments and docs directly above a rule attached to it.
+fn space_rules(items: Vec<Item<'_>>, blank_lines: usize) -> Vec<Item<'_>> {
+    let mut spaced = vec![];
+    let mut pending = vec![];
+
+    for item in items {
+        match item {
+            Item::Blank => {}
+            Item::Line(line) if line.starts_with("//!") => {
+                spaced.push(Item::Line(line));
+            }
+            Item::Line(line) => pending.push(Item::Line(line)),
+            Item::Rule(rule) => {
+                if !spaced.is_empty() {
+                    spaced.extend((0..blank_lines).map(|_| Item::Blank));
+                }
+                spaced.append(&mut pending);
+                spaced.push(Item::Rule(rule));
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        if !spaced.is_empty() {
+            spaced.extend((0..blank_lines).map(|_| Item::Blank));
+        }
+        spaced.append(&mut pending);
+    }
+
+    spaced
+}
+
+fn render_rule(output: &mut String, rule: &RuleLayout, name_width: usize, options: &FormatOptions) {
+    let padding = name_width.saturating_sub(rule.name.chars().count());
+    let header = format!("{}{} = {}{{", rule.name, " ".repeat(padding), rule.modifier);
+
+    if rule.body.contains('\n') {
+        let mut in_block = false;
+
+        output.push_str(&header);
+        for line in rule.body.lines().skip(1) {
+            if options.comments == CommentPolicy::Strip && is_comment_line(line, &mut in_block) {
+                continue;
+            }
+
+            let content = line.trim_start();
+            let indent = line.len() - content.len();
+            let indent = if content.starts_with('|') && indent % PEST_FMT_INDENT == 2 {
+                // pest_fmt hangs `| ` two spaces left of the choices.
+                (indent / PEST_FMT_INDENT + 1) * options.indent_width - 2
+            } else {
+                indent / PEST_FMT_INDENT * options.indent_width + indent % PEST_FMT_INDENT
+            };
+
+            output.push('\n');
+            if !content.is_empty() {
+                output.push_str(&" ".repeat(indent));
+                output.push_str(content);
+            }
+        }
+        output.push_str("\n}");
+        return;
+    }
+
+    let single = format!("{} {} }}", header, rule.body.trim());
+    let too_wide = options
+        .choice_wrap_width
+        .is_some_and(|width| single.chars().count() > width);
+
+    if !too_wide || rule.choices.is_empty() {
+        output.push_str(&single);
+        return;
+    }
+
+    // Later choices start with `| `, hung like pest_fmt hangs them.
+    let choice_indent = options.indent_width;
+    output.push_str(&header);
+    for (i, choice) in rule.choices.iter().enumerate() {
+        output.push('\n');
+        if i == 0 {
+            output.push_str(&" ".repeat(choice_indent));
+        } else {
+            output.push_str(&" ".repeat(choice_indent - 2));
+            output.push_str("| ");
+        }
+        output.push_str(choice);
+    }
+    output.push_str("\n}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_valid_grammars() {
+        let result = format_grammar("a={\"x\"~b}\nb={ASCII_DIGIT+}", &FormatOptions::default());
+        let FormatResult::Ok {
+            formatted,
+            diff,
+            idempotent,
+            equivalent,
+        } = result
+        else {
+            panic!("unexpected {:?}", result);
+        };
+
+        assert_eq!(formatted, "a = { \"x\" ~ b }\nb = { ASCII_DIGIT+ }\n");
+        assert_eq!(diff.len(), 4);
+        assert!(idempotent);
+        assert_eq!(equivalent, Some(true));
+    }
+
+    #[test]
+    fn invalid_grammars_have_no_equivalence_verdict() {
+        let result = format_grammar("a={b}", &FormatOptions::default());
+
+        assert!(matches!(
+            result,
+            FormatResult::Ok {
+                idempotent: true,
+                equivalent: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn reports_syntax_errors_instead_of_panicking() {
+        let errors = match format_grammar("a = { \"x\" ~ }\nb = {", &FormatOptions::default()) {
+            FormatResult::Error { errors } => errors,
+            result => panic!("unexpected {:?}", result),
+        };
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, DiagnosticCode::Syntax);
+        assert_eq!(errors[0].from, Position { line: 0, ch: 12 });
+    }
+
+    const GRAMMAR: &str = "//! Grammar docs.
+// Leading comment.
+a={\"x\"~b}
+/// Docs for long_name.
+long_name=@{ASCII_DIGIT+}
+c = _{ \"a\" | \"bb\" | \"ccc\" }
+
+
+d = { // trailing
+  \"x\" ~ (\"y\" | \"z\")
+  ~ \"w\" }
+e = { \"q\" }
+";
+
+    fn format_with(options: FormatOptions) -> String {
+        match format_grammar(GRAMMAR, &options) {
+            FormatResult::Ok {
+                formatted,
+                idempotent,
+                ..
+            } => {
+                assert!(idempotent, "{:?} is not idempotent", options);
+                formatted
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn default_options_match_pest_fmt() {
+        let expected = Formatter::new(GRAMMAR).format().unwrap();
+
+        assert_eq!(format_with(FormatOptions::default()), expected);
+    }
+
+    #[test]
+    fn indent_width_reindents_multi_line_rules() {
+        let formatted = format_with(FormatOptions {
+            indent_width: 2,
+            ..FormatOptions::default()
+        });
+
+        assert!(formatted.contains("d = {\n  // trailing\n  \"x\" ~ (\"y\" | \"z\") ~ \"w\"\n}\n"));
+    }
+
+    #[test]
+    fn wide_choices_are_wrapped() {
+        let formatted = format_with(FormatOptions {
+            choice_wrap_width: Some(20),
+            ..FormatOptions::default()
+        });
+
+        assert!(formatted.contains("c         = _{\n    \"a\"\n  | \"bb\"\n  | \"ccc\"\n}\n"));
+    }
+
+    #[test]
+    fn wrapped_choices_line_up_with_narrow_indents() {
+        let formatted = format_with(FormatOptions {
+            indent_width: 2,
+            choice_wrap_width: Some(20),
+            ..FormatOptions::default()
+        });
+        assert!(formatted.contains("c         = _{\n  \"a\"\n| \"bb\"\n| \"ccc\"\n}\n"));
+    }
+
+    #[test]
+    fn every_allowed_indent_width_is_idempotent() {
+        // The editor allows widths up to 16.
+        for indent_width in MIN_INDENT_WIDTH..=16 {
+            for choice_wrap_width in [None, Some(1), Some(20)] {
+                format_with(FormatOptions {
+                    indent_width,
+                    choice_wrap_width,
+                    ..FormatOptions::default()
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn narrower_indents_are_rejected() {
+        let options = FormatOptions {
+            indent_width: MIN_INDENT_WIDTH - 1,
+            ..FormatOptions::default()
+        };
+
+        let FormatResult::Error { errors } = format_grammar(GRAMMAR, &options) else {
+            panic!("{:?} was accepted", options);
+        };
+        assert_eq!(errors[0].code, DiagnosticCode::Options);
+    }
+
+    #[test]
+    fn alignment_can_be_disabled() {
+        let formatted = format_with(FormatOptions {
+            align_assignments: false,
+            ..FormatOptions::default()
+        });
+
+        assert!(formatted.contains("long_name = @{ ASCII_DIGIT+ }\nc = _{"));
+    }
+
+    #[test]
+    fn blank_lines_between_rules_are_normalized() {
+        let formatted = format_with(FormatOptions {
+            blank_lines_between_rules: Some(1),
+            ..FormatOptions::default()
+        });
+
+        assert_eq!(
+            formatted,
+            "//! Grammar docs.
+
+// Leading comment.
+a = { \"x\" ~ b }
+
+/// Docs for long_name.
+long_name = @{ ASCII_DIGIT+ }
+
+c = _{ \"a\" | \"bb\" | \"ccc\" }
+
+d = {
+    // trailing
+    \"x\" ~ (\"y\" | \"z\") ~ \"w\"
+}
+
+e = { \"q\" }
+"
+        );
+    }
+
+    #[test]
+    fn comments_can_be_stripped() {
+        let formatted = format_with(FormatOptions {
+            comments: CommentPolicy::Strip,
+            ..FormatOptions::default()
+        });
+
+        assert!(!formatted.contains("Leading comment"));
+        assert!(!formatted.contains("trailing"));
+        assert!(formatted.contains("//! Grammar docs."));
+        assert!(formatted.contains("/// Docs for long_name."));
+    }
+
+    #[test]
+    fn range_formatting_touches_only_overlapping_rules() {
+        let grammar = "a={\"a\"}\nb={\"b\"~a}\nc={\"c\"}\n";
+        let target = FormatTarget::Range { start: 10, end: 12 };
+
+        assert_eq!(
+            format_range(grammar, &target, &FormatOptions::default()),
+            RangeFormatResult::Ok {
+                edit: Some(TextEdit {
+                    from: Position { line: 1, ch: 0 },
+                    to: Position { line: 1, ch: 9 },
+                    text: "b = { \"b\" ~ a }".to_owned(),
+                })
+            }
+        );
+    }
+
+    #[test]
+    fn range_output_matches_the_whole_document() {
+        let options = FormatOptions {
+            choice_wrap_width: Some(20),
+            ..FormatOptions::default()
+        };
+        let whole = format_with(options.clone());
+        let targets = ["a", "long_name", "c", "d"]
+            .map(|rule| FormatTarget::Rule {
+                rule: rule.to_owned(),
+            })
+            .into_iter()
+            .chain([FormatTarget::Range {
+                start: GRAMMAR.find("c =").unwrap(),
+                end: GRAMMAR.find("e =").unwrap(),
+            }]);
+
+        for target in targets {
+            let edit = match format_range(GRAMMAR, &target, &options) {
+                RangeFormatResult::Ok { edit: Some(edit) } => edit,
+                result => panic!("unexpected {:?} for {:?}", result, target),
+            };
+
+            assert!(
+                whole.starts_with(&format!("{}\n", edit.text))
+                    || whole.contains(&format!("\n{}\n", edit.text)),
+                "{:?} is not in the whole output",
+                edit.text
+            );
+        }
+    }
+
+    #[test]
+    fn range_formatting_by_rule_name() {
+        let grammar = "a={\"a\"}\nb = { \"b\" }\n";
+        let options = FormatOptions::default();
+
+        let edit = |rule: &str| match format_range(
+            grammar,
+            &FormatTarget::Rule {
+                rule: rule.to_owned(),
+            },
+            &options,
+        ) {
+            RangeFormatResult::Ok { edit } => edit,
+            result => panic!("unexpected {:?}", result),
+        };
+
+        assert_eq!(edit("a").unwrap().text, "a = { \"a\" }");
+        assert_eq!(edit("b"), None);
+        assert_eq!(edit("missing"), None);
+    }
+}
@@ -0,0 +1,571 @@
+//! Grammar compilation and input parsing, independent of the DOM.
+
+use std::cell::OnceCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use pest::iterators::Pairs;
+use pest_meta::ast::Rule as AstRule;
+use pest_meta::optimizer::{self, OptimizedRule};
+use pest_meta::parser::{self, Rule};
+use pest_meta::validator;
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostic::{convert_error, Diagnostic, DiagnosticCode, InputDiagnostic};
+use crate::limits::{LimitError, ParseLimits};
+use crate::position::{LineIndex, Position};
+use crate::stats::now_ms;
+use crate::tree::ParseTree;
+use crate::vm::{ParseFailure, Vm};
+
+/// A grammar that passed validation, ready to parse inputs.
+pub struct CompiledGrammar {
+    rule_names: Vec<String>,
+    rules: Vec<OptimizedRule>,
+    /// Built on the first parse, so a page that only lints grammars and
+    /// leaves parsing to a worker never builds one.
+    vm: OnceCell<Vm>,
+}
+
+impl CompiledGrammar {
+    /// Names of the grammar's rules, in definition order.
+    pub fn rule_names(&self) -> &[String] {
+        &self.rule_names
+    }
+
+    /// Parses `input` starting at `rule`, without limits.
+    pub fn parse<'a>(
+        &'a self,
+        rule: &'a str,
+        input: &'a str,
+    ) -> Result<Pairs<'a, &'a str>, Box<InputDiagnostic>> {
+        match self.vm().parse(rule, input, &ParseLimits::unlimited()) {
+            Ok(pairs) => Ok(pairs),
+            Err(ParseFailure::Error(error)) => {
+                Err(Box::new(InputDiagnostic::from_error(error, input)))
+            }
+            Err(ParseFailure::Limit(_)) => unreachable!("unlimited parses hit no limit"),
+        }
+    }
+
+    /// Parses `input` starting at `rule` within `limits`, timing the parse
+    /// but not building the VM on first use.
+    fn run(
+        &self,
+        rule: &str,
+        input: &str,
+        limits: &ParseLimits,
+        compile_ms: Option<f64>,
+    ) -> ParseOutcome {
+        let vm = self.vm();
+        let started = now_ms();
+
+        match vm.parse(rule, input, limits) {
+            Ok(pairs) => {
+                let tree = ParseTree::new(pairs);
+                ParseOutcome::Ok {
+                    tree,
+                    compile_ms,
+                    parse_ms: now_ms() - started,
+                }
+            }
+            Err(ParseFailure::Error(error)) => ParseOutcome::Error {
+                diagnostic: InputDiagnostic::from_error(error, input),
+            },
+            Err(ParseFailure::Limit(error)) => ParseOutcome::Limit { error },
+        }
+    }
+
+    fn vm(&self) -> &Vm {
+        self.vm.get_or_init(|| Vm::new(self.rules.clone()))
+    }
+}
+
+/// The result of parsing an input. Successful parses are printed by the
+/// caller, so printer settings can change without parsing again.
+///
+/// Rule definitions are not part of the outcome: they belong to the grammar,
+/// so they are fetched once per compile with `definitions()` rather than
+/// sent back with every parse of the same grammar.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum ParseOutcome {
+    /// Timings are in milliseconds, measured where the grammar was compiled
+    /// and run, so they leave out any time spent getting there.
+    /// `compile_ms` is `None` if the grammar came out of a `GrammarCache`
+    /// instead of being compiled.
+    Ok {
+        tree: ParseTree,
+        #[serde(rename = "compileMs")]
+        compile_ms: Option<f64>,
+        #[serde(rename = "parseMs")]
+        parse_ms: f64,
+    },
+    Error {
+        diagnostic: InputDiagnostic,
+    },
+    /// The parse was stopped by one of its `ParseLimits`.
+    Limit {
+        error: LimitError,
+    },
+    /// A newer parse request superseded this one before it finished.
+    Cancelled,
+}
+
+/// Where a rule is defined in the grammar text, from its name to its closing
+/// brace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuleDefinition {
+    pub name: String,
+    pub from: Position,
+    pub to: Position,
+}
+
+/// Validates and optimizes `grammar`.
+pub fn compile(grammar: &str) -> Result<CompiledGrammar, Vec<Diagnostic>> {
+    compile_with_definitions(grammar).map(|(compiled, _)| compiled)
+}
+
+fn compile_with_definitions(
+    grammar: &str,
+) -> Result<(CompiledGrammar, Vec<RuleDefinition>), Vec<Diagnostic>> {
+    let (ast, definitions) = analyze(grammar)?;
+    let rules = optimizer::optimize(ast);
+
+    let compiled = CompiledGrammar {
+        rule_names: rules.iter().map(|rule| rule.name.clone()).collect(),
+        rules,
+        vm: OnceCell::new(),
+    };
+
+    Ok((compiled, definitions))
+}
+
+/// How many grammar texts a `GrammarCache` remembers. The least recently
+/// used one is forgotten first.
+const CACHE_CAPACITY: usize = 16;
+
+/// Compiled grammars and grammar diagnostics keyed by the grammar text.
+///
+/// Texts that differ only in ways the optimizer discards, like comments and
+/// layout, share one `CompiledGrammar`, so callers can tell with `Rc::ptr_eq`
+/// whether the rules actually changed.
+#[derive(Default)]
+pub struct GrammarCache {
+    entries: HashMap<String, CacheEntry>,
+    order: VecDeque<String>,
+}
+
+/// A cached compilation: the possibly shared grammar and the rule
+/// definitions of this particular text.
+#[derive(Clone)]
+pub struct CachedGrammar {
+    pub grammar: Rc<CompiledGrammar>,
+    pub definitions: Rc<[RuleDefinition]>,
+    /// Wall time of compiling this text in milliseconds, or `None` if it
+    /// was found in the cache.
+    pub compile_ms: Option<f64>,
+}
+
+impl CachedGrammar {
+    /// Parses `input` starting at `rule` within `limits`, timing the parse
+    /// but not building the VM on first use.
+    pub fn run(&self, rule: &str, input: &str, limits: &ParseLimits) -> ParseOutcome {
+        self.grammar.run(rule, input, limits, self.compile_ms)
+    }
+}
+
+type CacheEntry = Result<CachedGrammar, Vec<Diagnostic>>;
+
+impl GrammarCache {
+    pub fn new() -> GrammarCache {
+        GrammarCache::default()
+    }
+
+    /// Compiles `grammar`, or returns the cached result for the same text.
+    pub fn compile(&mut self, grammar: &str) -> CacheEntry {
+        if let Some(entry) = self.entries.get(grammar) {
+            let entry = entry.clone().map(|cached| CachedGrammar {
+                compile_ms: None,
+                ..cached
+            });
+            if let Some(used) = self.order.iter().position(|text| text == grammar) {
+                let text = self.order.remove(used).unwrap();
+                self.order.push_back(text);
+            }
+
+            return entry;
+        }
+
+        let started = now_ms();
+        let entry = compile_with_definitions(grammar).map(|(compiled, definitions)| {
+            let grammar = self
+                .entries
+                .values()
+                .filter_map(|entry| entry.as_ref().ok())
+                .find(|cached| cached.grammar.rules == compiled.rules)
+                .map(|cached| Rc::clone(&cached.grammar))
+                .unwrap_or_else(|| Rc::new(compiled));
+
+            CachedGrammar {
+                grammar,
+                definitions: definitions.into(),
+                compile_ms: Some(now_ms() - started),
+            }
+        });
+
+        if self.order.len() == CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.push_back(grammar.to_owned());
+        self.entries.insert(grammar.to_owned(), entry.clone());
+
+        entry
+    }
+}
+
+/// Parses and validates `grammar` without building a VM for it.
+pub fn check(grammar: &str) -> Result<Vec<AstRule>, Vec<Diagnostic>> {
+    analyze(grammar).map(|(ast, _)| ast)
+}
+
+fn analyze(grammar: &str) -> Result<(Vec<AstRule>, Vec<RuleDefinition>), Vec<Diagnostic>> {
+    let lines = LineIndex::new(grammar);
+    let pairs = parser::parse(Rule::grammar_rules, grammar)
+        .map_err(|error| vec![convert_error(error, DiagnosticCode::Syntax, &lines)])?;
+
+    validator::validate_pairs(pairs.clone()).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|e| convert_error(e, DiagnosticCode::Validation, &lines))
+            .collect::<Vec<_>>()
+    })?;
+
+    // `consume_rules` drops the spans, so take them from the pairs.
+    let definitions = pairs
+        .clone()
+        .filter(|pair| pair.as_rule() == Rule::grammar_rule)
+        .filter_map(|pair| {
+            let name = pair.clone().into_inner().next()?;
+            let span = pair.as_span();
+
+            (name.as_rule() == Rule::identifier).then(|| RuleDefinition {
+                name: name.as_str().to_owned(),
+                from: lines.position(span.start()),
+                to: lines.position(span.end()),
+            })
+        })
+        .collect();
+
+    let ast = parser::consume_rules(pairs).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|e| convert_error(e, DiagnosticCode::Semantic, &lines))
+            .collect::<Vec<_>>()
+    })?;
+
+    Ok((ast, definitions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::LimitKind;
+
+    const MALFORMED: &[&str] = &[
+        "a",
+        "a =",
+        "a = {",
+        "a = { \"x\" ",
+        "a = { \"x }",
+        "a = { 'x }",
+        "a = { b }",
+        "a = { a }",
+        "a = { \"x\"* * }",
+        "a = { (\"x\"*)* }",
+        "a = { '\u{1F600}' ",
+        "a = _{ }",
+        "a = { PUSH( }",
+        "a = { #tag = }",
+        "a = { \"x\" }\na = { \"y\" }",
+        "a = { \"x\" }\r\nb = { | }",
+        "a = { \"x\" } }",
+        "WHITESPACE = { \"\"* }",
+        "ANY = { \"x\" }",
+        "a = { ^ }",
+        "a = @{ \"x\" ~ }",
+    ];
+
+    /// Grammars that pass validation but give the optimizer unusual shapes
+    /// to rewrite, each with an input its first rule matches.
+    const OPTIMIZER_STRESS: &[(&str, &str)] = &[
+        ("a = { (\"x\" | \"y\") | (\"z\" | (\"w\" | \"v\")) }", "v"),
+        ("a = { \"x\" ~ \"y\" | \"x\" ~ \"z\" | \"x\" }", "xz"),
+        ("a = { (!\"*/\" ~ ANY)* ~ \"*/\" }", "ab*/"),
+        ("a = { (!(\"a\" | \"b\") ~ ANY)* ~ \"b\" }", "xyb"),
+        ("a = { !!\"x\" ~ &&\"x\" ~ !(!\"x\") ~ \"x\" }", "x"),
+        (
+            "a = { \"a\"{3} ~ \"b\"{1, 2} ~ \"c\"{, 2} ~ \"d\"{2, } }",
+            "aaabbcdd",
+        ),
+        ("a = { \"x\"{0, 200} ~ EOI }", "xxxx"),
+        ("a = { ((((((((((\"x\")))))))))) ~ (((\"y\"?)?)?) }", "xy"),
+        ("a = { b ~ b }\nb = _{ c }\nc = _{ d }\nd = { \"x\" }", "xx"),
+        ("a = @{ b* }\nb = ${ \"x\" ~ c? }\nc = !{ \"y\" }", "xxy"),
+        (
+            "a = { PUSH(\"x\" | \"y\") ~ PEEK[..] ~ PEEK[0..1] ~ POP }",
+            "yyyy",
+        ),
+        ("a = { ^\"ab\" ~ ^\"\" ~ '\\u{00}'..'\\u{10FFFF}' }", "AbZ"),
+        ("a = { #x = (#y = \"a\" | #z = \"b\")+ }", "ab"),
+        ("a = { \"\" ~ \"x\" ~ \"\" }", "x"),
+    ];
+
+    #[test]
+    fn malformed_grammars_produce_diagnostics() {
+        let mut cache = GrammarCache::new();
+
+        for grammar in MALFORMED {
+            let errors = match cache.compile(grammar) {
+                Ok(_) => panic!("{:?} should not compile", grammar),
+                Err(errors) => errors,
+            };
+
+            assert!(!errors.is_empty(), "{:?} gave no diagnostics", grammar);
+            for error in errors {
+                assert!(!error.message.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn optimizer_stress_grammars_compile_and_parse() {
+        let mut cache = GrammarCache::new();
+
+        for (grammar, input) in OPTIMIZER_STRESS {
+            let compiled = match cache.compile(grammar) {
+                Ok(compiled) => compiled,
+                Err(errors) => panic!("{:?} did not compile: {:?}", grammar, errors),
+            };
+            let rule = &compiled.grammar.rule_names()[0];
+            let outcome = compiled.run(rule, input, &ParseLimits::default());
+
+            assert!(
+                matches!(outcome, ParseOutcome::Ok { .. }),
+                "{:?} on {:?} gave {:?}",
+                grammar,
+                input,
+                outcome
+            );
+        }
+    }
+
+    #[test]
+    fn parsing_errors_use_meta_rule_names() {
+        let errors = check("a = ").unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, DiagnosticCode::Syntax);
+        assert!(errors[0].message.starts_with("expected"));
+        assert!(errors[0].message.contains("`{`"));
+    }
+
+    #[test]
+    fn valid_grammar_compiles() {
+        let ast = check("a = { \"x\" ~ b }\nb = @{ ASCII_DIGIT+ }").unwrap();
+
+        assert_eq!(ast.len(), 2);
+    }
+
+    #[test]
+    fn compiled_grammars_parse_inputs() {
+        let grammar =
+            compile("list = { item ~ (\",\" ~ item)* }\nitem = { ASCII_DIGIT+ }").unwrap();
+
+        assert_eq!(grammar.rule_names(), ["list", "item"]);
+        assert_eq!(grammar.parse("list", "1,23").unwrap().count(), 1);
+
+        let diagnostic = grammar.parse("list", "x").unwrap_err();
+        assert_eq!((diagnostic.start, diagnostic.end), (0, 0));
+        assert_eq!(diagnostic.expected, ["item"]);
+    }
+
+    #[test]
+    fn runs_return_outcomes() {
+        let grammar = GrammarCache::new()
+            .compile("item = { ASCII_DIGIT+ }")
+            .unwrap();
+
+        let ParseOutcome::Ok {
+            tree,
+            compile_ms,
+            parse_ms,
+        } = grammar.run("item", "12", &ParseLimits::default())
+        else {
+            panic!("expected a successful parse");
+        };
+        assert_eq!(tree.nodes.len(), 1);
+        assert!(compile_ms.is_some_and(|ms| ms >= 0.0) && parse_ms >= 0.0);
+        assert!(matches!(
+            grammar.run("item", "x", &ParseLimits::default()),
+            ParseOutcome::Error { .. }
+        ));
+    }
+
+    #[test]
+    fn depth_limits_name_the_rule_stack() {
+        let grammar = GrammarCache::new()
+            .compile("nested = { \"(\" ~ nested? ~ \")\" }")
+            .unwrap();
+        let limits = ParseLimits {
+            call_limit: None,
+            depth_limit: Some(3),
+        };
+
+        assert!(matches!(
+            grammar.run("nested", "(())", &limits),
+            ParseOutcome::Ok { .. }
+        ));
+
+        let outcome = grammar.run("nested", "((((()))))", &limits);
+        let ParseOutcome::Limit { error } = outcome else {
+            panic!("expected a limit error, got {:?}", outcome);
+        };
+        assert_eq!(error.kind, LimitKind::Depth);
+        assert_eq!(error.rules, ["nested"; 4]);
+        assert_eq!(
+            error.report(),
+            "recursion depth limit of 3 reached in rule `nested`\n\
+             rule stack: nested > nested > nested > nested"
+        );
+    }
+
+    #[test]
+    fn depth_limits_count_only_nested_rules() {
+        // `b` is called from inside a repetition after `a` returned from a
+        // plain sequence, and `x` fails before `a` is tried; neither is
+        // nested in the rule before it.
+        let grammar = GrammarCache::new()
+            .compile(
+                "top = { (x | a) ~ (\",\" ~ b)* }\nx = { \"x\" ~ a }\na = { \"a\" }\nb = { \"b\" }",
+            )
+            .unwrap();
+        let limits = ParseLimits {
+            call_limit: None,
+            depth_limit: Some(2),
+        };
+
+        assert!(matches!(
+            grammar.run("top", "a,b,b", &limits),
+            ParseOutcome::Ok { .. }
+        ));
+
+        let outcome = grammar.run("top", "xa", &limits);
+        let ParseOutcome::Limit { error } = outcome else {
+            panic!("expected a limit error, got {:?}", outcome);
+        };
+        assert_eq!(error.rules, ["top", "x", "a"]);
+    }
+
+    #[test]
+    fn call_limits_count_rule_entries() {
+        let grammar = GrammarCache::new()
+            .compile("list = { item* }\nitem = { ASCII_DIGIT }")
+            .unwrap();
+        let limits = |call_limit| ParseLimits {
+            call_limit: Some(call_limit),
+            depth_limit: None,
+        };
+
+        // `list`, then `item` and `ASCII_DIGIT` per digit and once more
+        // where they fail.
+        assert!(matches!(
+            grammar.run("list", "12", &limits(7)),
+            ParseOutcome::Ok { .. }
+        ));
+
+        let outcome = grammar.run("list", "12", &limits(6));
+        let ParseOutcome::Limit { error } = outcome else {
+            panic!("expected a limit error, got {:?}", outcome);
+        };
+        assert_eq!(error.kind, LimitKind::Call);
+        assert_eq!(error.rules, ["list", "item"]);
+    }
+
+    #[test]
+    fn cache_shares_grammars_with_equal_rules() {
+        let mut cache = GrammarCache::new();
+
+        let first = cache.compile("a = { \"x\" }").unwrap();
+        let relaid = cache.compile("// comment\na   =   {\"x\"}").unwrap();
+        let changed = cache.compile("a = { \"y\" }").unwrap();
+
+        assert!(Rc::ptr_eq(&first.grammar, &relaid.grammar));
+        assert!(!Rc::ptr_eq(&first.grammar, &changed.grammar));
+        assert_eq!(relaid.definitions[0].from, Position { line: 1, ch: 0 });
+        assert!(cache.compile("a = {").is_err());
+
+        for i in 0..CACHE_CAPACITY {
+            let _ = cache.compile(&format!("r{} = {{ \"x\" }}", i));
+        }
+        let recompiled = cache.compile("a = { \"x\" }").unwrap();
+        assert!(!Rc::ptr_eq(&first.grammar, &recompiled.grammar));
+    }
+
+    #[test]
+    fn cache_hits_are_reported_and_kept_longest() {
+        let mut cache = GrammarCache::new();
+        let item = "item = { ASCII_DIGIT+ }";
+
+        let first = cache.compile(item).unwrap();
+        assert!(first.compile_ms.is_some());
+        let relaid = cache.compile("item = {ASCII_DIGIT+}").unwrap();
+        assert!(relaid.compile_ms.is_some());
+
+        let hit = cache.compile(item).unwrap();
+        assert_eq!(hit.compile_ms, None);
+        assert!(matches!(
+            hit.run("item", "1", &ParseLimits::default()),
+            ParseOutcome::Ok {
+                compile_ms: None,
+                ..
+            }
+        ));
+
+        // `item` was used after the relaid text, so the relaid text is
+        // forgotten first.
+        for i in 0..CACHE_CAPACITY - 2 {
+            let _ = cache.compile(&format!("r{} = {{ \"x\" }}", i));
+        }
+        let _ = cache.compile("full = { \"x\" }");
+        assert_eq!(cache.compile(item).unwrap().compile_ms, None);
+        assert!(cache
+            .compile("item = {ASCII_DIGIT+}")
+            .unwrap()
+            .compile_ms
+            .is_some());
+    }
+
+    #[test]
+    fn definitions_span_whole_rules() {
+        let (_, definitions) = analyze("/// Docs\na = { \"x\" }\nb = @{\n    a*\n}\n").unwrap();
+
+        assert_eq!(
+            definitions,
+            [
+                RuleDefinition {
+                    name: String::from("a"),
+                    from: Position { line: 1, ch: 0 },
+                    to: Position { line: 1, ch: 11 },
+                },
+                RuleDefinition {
+                    name: String::from("b"),
+                    from: Position { line: 2, ch: 0 },
+                    to: Position { line: 4, ch: 1 },
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Drawings of parse results: Graphviz DOT descriptions, and SVG trees laid
+//! out here so no Graphviz install is needed.
+
+use crate::export::escape_xml;
+use crate::tree::ParseTree;
+
+/// Leaf text longer than this is cut short in node labels.
+const LABEL_TEXT_CHARS: usize = 24;
+
+// SVG layout metrics, in pixels. Labels use a monospace font, so a line is
+// as wide as its char count times `CHAR_WIDTH`.
+const FONT_SIZE: f64 = 12.0;
+const CHAR_WIDTH: f64 = 7.2;
+const LINE_HEIGHT: f64 = 16.0;
+const PADDING: f64 = 6.0;
+const NODE_GAP: f64 = 12.0;
+const LEVEL_GAP: f64 = 32.0;
+const MARGIN: f64 = 10.0;
+
+/// Describes `tree`, parsed from `input`, as a Graphviz digraph with one box
+/// per pair, labelled with its rule, node tag and, for leaves, text.
+pub fn tree_to_dot(tree: &ParseTree, input: &str) -> String {
+    let mut dot = String::from(
+        "digraph parse {\n  ordering=out;\n  node [shape=box, fontname=\"monospace\"];\n",
+    );
+
+    for (index, node) in tree.nodes.iter().enumerate() {
+        let label: Vec<_> = label_lines(tree, input, index)
+            .iter()
+            .map(|line| escape_dot(line))
+            .collect();
+        dot.push_str(&format!(
+            "  n{} [label=\"{}\"];\n",
+            index,
+            label.join("\\n")
+        ));
+
+        if let Some(parent) = node.parent {
+            dot.push_str(&format!("  n{} -> n{};\n", parent, index));
+        }
+    }
+
+    dot.push('}');
+    dot
+}
+
+/// Draws `tree`, parsed from `input`, as a top-down SVG tree.
+///
+/// Every subtree gets a column as wide as the wider of its own box and its
+/// children's columns side by side. Both the box and the children are
+/// centered in the column.
+pub fn tree_to_svg(tree: &ParseTree, input: &str) -> String {
+    let labels: Vec<_> = (0..tree.nodes.len())
+        .map(|index| label_lines(tree, input, index))
+        .collect();
+    let sizes: Vec<_> = labels
+        .iter()
+        .map(|lines| {
+            let chars = lines.iter().map(|line| line.chars().count()).max();
+            (
+                chars.unwrap_or(0) as f64 * CHAR_WIDTH + 2.0 * PADDING,
+                lines.len() as f64 * LINE_HEIGHT + 2.0 * PADDING,
+            )
+        })
+        .collect();
+    let level_height = sizes.iter().map(|&(_, height)| height).fold(0.0, f64::max) + LEVEL_GAP;
+
+    // Children come after their parents, so walking backwards settles every
+    // column before its parent's.
+    let mut columns = vec![0.0; tree.nodes.len()];
+    for (index, node) in tree.nodes.iter().enumerate().rev() {
+        let children = node.children.iter().map(|&child| columns[child]);
+        let children = children.sum::<f64>() + gaps(node.children.len());
+        columns[index] = sizes[index].0.max(children);
+    }
+
+    // Then walk forwards, placing every node's children inside its column.
+    let roots: Vec<_> = tree.roots().collect();
+    let mut lefts = vec![0.0; tree.nodes.len()];
+    let mut depths = vec![0; tree.nodes.len()];
+    place(&roots, MARGIN, &columns, &mut lefts);
+    for (index, node) in tree.nodes.iter().enumerate() {
+        let children: f64 = node.children.iter().map(|&child| columns[child]).sum();
+        let slack = columns[index] - children - gaps(node.children.len());
+        place(
+            &node.children,
+            lefts[index] + slack / 2.0,
+            &columns,
+            &mut lefts,
+        );
+        if let Some(parent) = node.parent {
+            depths[index] = depths[parent] + 1;
+        }
+    }
+
+    let center = |index: usize| lefts[index] + columns[index] / 2.0;
+    let top = |index: usize| MARGIN + depths[index] as f64 * level_height;
+
+    let width = roots.iter().map(|&root| columns[root]).sum::<f64>() + gaps(roots.len());
+    let width = width + 2.0 * MARGIN;
+    let levels = depths.iter().max().map_or(0, |&depth| depth + 1);
+    let height = levels as f64 * level_height - LEVEL_GAP + 2.0 * MARGIN;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"monospace\" font-size=\"{2}\">\n",
+        width.max(0.0),
+        height.max(0.0),
+        FONT_SIZE
+    );
+
+    svg.push_str("  <g stroke=\"#888\" fill=\"none\">\n");
+    for (index, node) in tree.nodes.iter().enumerate() {
+        if let Some(parent) = node.parent {
+            svg.push_str(&format!(
+                "    <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>\n",
+                center(parent),
+                top(parent) + sizes[parent].1,
+                center(index),
+                top(index)
+            ));
+        }
+    }
+    svg.push_str("  </g>\n");
+
+    for (index, node) in tree.nodes.iter().enumerate() {
+        let (box_width, box_height) = sizes[index];
+        svg.push_str(&format!(
+            "  <g>\n    <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"3\" fill=\"#fff\" stroke=\"#333\"/>\n",
+            center(index) - box_width / 2.0,
+            top(index),
+            box_width,
+            box_height
+        ));
+
+        for (i, line) in labels[index].iter().enumerate() {
+            let style = match i {
+                0 => " font-weight=\"bold\" fill=\"#000\"",
+                1 if node.tag.is_some() => " fill=\"#c05020\"",
+                _ => " fill=\"#555\"",
+            };
+            svg.push_str(&format!(
+                "    <text x=\"{}\" y=\"{}\" text-anchor=\"middle\"{}>{}</text>\n",
+                center(index),
+                top(index) + PADDING + (i as f64 + 0.75) * LINE_HEIGHT,
+                style,
+                escape_xml(line)
+            ));
+        }
+        svg.push_str("  </g>\n");
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Sets the left edges of `nodes`, placed side by side from `left`.
+fn place(nodes: &[usize], mut left: f64, columns: &[f64], lefts: &mut [f64]) {
+    for &node in nodes {
+        lefts[node] = left;
+        left += columns[node] + NODE_GAP;
+    }
+}
+
+fn gaps(count: usize) -> f64 {
+    count.saturating_sub(1) as f64 * NODE_GAP
+}
+
+/// The rule name, the node tag if any and, for leaves, the quoted text.
+fn label_lines(tree: &ParseTree, input: &str, index: usize) -> Vec<String> {
+    let node = &tree.nodes[index];
+    let mut lines = vec![node.rule.clone()];
+
+    if let Some(tag) = &node.tag {
+        lines.push(format!("#{}", tag));
+    }
+    if node.children.is_empty() {
+        let text = &input[node.start..node.end];
+        lines.push(match text.char_indices().nth(LABEL_TEXT_CHARS) {
+            Some((end, _)) => format!("{:?}…", &text[..end]),
+            None => format!("{:?}", text),
+        });
+    }
+
+    lines
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::fixture::{pair_list, pair_tree};
+
+    use super::*;
+
+    /// The `x`, `y` and `width` of every box in `svg`, in order.
+    fn rects(svg: &str) -> Vec<(f64, f64, f64)> {
+        svg.lines()
+            .filter_map(|line| line.trim().strip_prefix("<rect "))
+            .map(|rect| {
+                let attribute = |name: &str| -> f64 {
+                    let start = rect.find(&format!("{}=\"", name)).unwrap() + name.len() + 2;
+                    rect[start..].split('"').next().unwrap().parse().unwrap()
+                };
+                (attribute("x"), attribute("y"), attribute("width"))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dot_labels_rules_tags_and_text() {
+        assert_eq!(
+            tree_to_dot(&pair_tree("ASCII_DIGIT+", "ab=12"), "ab=12"),
+            "digraph parse {\n  ordering=out;\n  node [shape=box, fontname=\"monospace\"];\n  n0 [label=\"pair\"];\n  n1 [label=\"key\"];\n  n0 -> n1;\n  n2 [label=\"ident\\n#name\\n\\\"ab\\\"\"];\n  n1 -> n2;\n  n3 [label=\"value\\n\\\"12\\\"\"];\n  n0 -> n3;\n}"
+        );
+    }
+
+    #[test]
+    fn svg_centers_parents_over_children() {
+        let svg = tree_to_svg(&pair_tree("ASCII_DIGIT+", "ab=12"), "ab=12");
+        let rects = rects(&svg);
+        let center = |(x, _, width): (f64, f64, f64)| x + width / 2.0;
+
+        assert_eq!(rects.len(), 4);
+        // `pair` sits above `key` and `value`, centered between them.
+        assert!(rects[0].1 < rects[1].1);
+        assert_eq!(rects[1].1, rects[3].1);
+        assert!(rects[1].0 + rects[1].2 < rects[3].0);
+        assert_eq!(
+            center(rects[0]),
+            (center(rects[1]) + center(rects[3])) / 2.0
+        );
+        assert!(svg.contains(">#name</text>"));
+        assert!(svg.contains(">&quot;ab&quot;</text>"));
+    }
+
+    #[test]
+    fn labels_escape_quotes_backslashes_and_markup() {
+        let input = "ab=\"\\\n\t<&>'";
+        let tree = pair_tree("ANY+", input);
+
+        assert!(tree_to_dot(&tree, input)
+            .contains("n3 [label=\"value\\n\\\"\\\\\\\"\\\\\\\\\\\\n\\\\t<&>'\\\"\"];"));
+        assert!(tree_to_svg(&tree, input)
+            .contains(">&quot;\\&quot;\\\\\\n\\t&lt;&amp;&gt;'&quot;</text>"));
+    }
+
+    #[test]
+    fn non_ascii_text_is_kept_and_sized_by_chars() {
+        let input = "ab=é😀ñ";
+        let tree = pair_tree("ANY+", input);
+        let svg = tree_to_svg(&tree, input);
+
+        assert!(tree_to_dot(&tree, input).contains("n3 [label=\"value\\n\\\"é😀ñ\\\"\"];"));
+        assert!(svg.contains(">&quot;é😀ñ&quot;</text>"));
+        // Boxes are sized by chars, so `"é😀ñ"` takes as much room as `"xyz"`.
+        let ascii = tree_to_svg(&pair_tree("ANY+", "ab=xyz"), "ab=xyz");
+        assert_eq!(rects(&svg)[3], rects(&ascii)[3]);
+    }
+
+    #[test]
+    fn roots_are_laid_out_side_by_side() {
+        let input = "ab=1;cd=23";
+        let tree = pair_list("ASCII_DIGIT+", input);
+
+        let dot = tree_to_dot(&tree, input);
+        assert!(dot.contains("  n4 [label=\"pair\"];\n  n5 [label=\"key\"];\n  n4 -> n5;\n"));
+        assert!(!dot.contains("-> n4"));
+
+        let rects = rects(&tree_to_svg(&tree, input));
+        assert_eq!(rects.len(), 8);
+        assert_eq!(rects[0].1, rects[4].1);
+        assert!(rects[3].0 + rects[3].2 < rects[5].0);
+    }
+
+    #[test]
+    fn empty_trees_draw_nothing() {
+        assert_eq!(
+            tree_to_dot(&ParseTree::default(), ""),
+            "digraph parse {\n  ordering=out;\n  node [shape=box, fontname=\"monospace\"];\n}"
+        );
+
+        let svg = tree_to_svg(&ParseTree::default(), "");
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>"));
+        assert!(rects(&svg).is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+//! Highlights a span of the input pane from a backdrop behind its textarea,
+//! since a textarea can't style parts of its own text.
+
+use std::cell::Cell;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, HtmlTextAreaElement, Text};
+
+use crate::position::byte_to_utf16;
+
+pub struct InputHighlight {
+    backdrop: Element,
+    input: HtmlTextAreaElement,
+    /// The input generation whose text the backdrop holds, if any.
+    generation: Cell<Option<u64>>,
+}
+
+impl InputHighlight {
+    /// Finds the `.editor-input-highlight` backdrop under `root`, if any.
+    pub fn find(root: &Element, input: &HtmlTextAreaElement) -> Option<InputHighlight> {
+        Some(InputHighlight {
+            backdrop: root
+                .query_selector(".editor-input-highlight")
+                .unwrap_throw()?,
+            input: input.clone(),
+            generation: Cell::new(None),
+        })
+    }
+
+    /// Highlights bytes `start..end` of `text`, the input's value as of
+    /// `generation`. The backdrop only copies `text` when the generation
+    /// changed; otherwise just the mark moves.
+    pub fn show(&self, generation: u64, text: &str, start: usize, end: usize) {
+        if self.generation.get() == Some(generation) {
+            self.clear();
+        } else {
+            // A trailing newline in a textarea still takes up a line.
+            self.backdrop.set_text_content(Some(&format!("{}\n", text)));
+            self.generation.set(Some(generation));
+        }
+
+        let start_utf16 = byte_to_utf16(text, start) as u32;
+        let end_utf16 = byte_to_utf16(text, end) as u32;
+        let whole: Text = self
+            .backdrop
+            .first_child()
+            .expect_throw("empty highlight backdrop")
+            .unchecked_into();
+        let marked = whole.split_text(start_utf16).unwrap_throw();
+        let after = marked.split_text(end_utf16 - start_utf16).unwrap_throw();
+
+        let document = self.backdrop.owner_document().expect_throw("no document");
+        let mark = document.create_element("mark").unwrap_throw();
+        self.backdrop
+            .insert_before(&mark, Some(&after))
+            .unwrap_throw();
+        mark.append_child(&marked).unwrap_throw();
+
+        self.sync_scroll();
+        if start == end {
+            // Keep empty spans visible as a caret-like mark.
+            mark.class_list().add_1("empty").unwrap_throw();
+        }
+    }
+
+    /// Removes the mark, leaving the backdrop's text in one piece for the
+    /// next `show`.
+    pub fn clear(&self) {
+        let mark = match self.backdrop.query_selector("mark").unwrap_throw() {
+            Some(mark) => mark,
+            None => return,
+        };
+        while let Some(child) = mark.first_child() {
+            self.backdrop
+                .insert_before(&child, Some(&mark))
+                .unwrap_throw();
+        }
+        mark.remove();
+        self.backdrop.normalize();
+    }
+
+    /// Scrolls the backdrop along with the textarea.
+    pub fn sync_scroll(&self) {
+        self.backdrop.set_scroll_top(self.input.scroll_top());
+        self.backdrop.set_scroll_left(self.input.scroll_left());
+    }
+}
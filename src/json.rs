@@ -0,0 +1,320 @@
+//! JSON rendering of parse results, in the layout of pest's
+//! `Pairs::to_json`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::position::{LineIndex, Position};
+use crate::tree::ParseTree;
+
+/// Fields added on top of the `Pairs::to_json` layout. The defaults add none.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JsonOptions {
+    /// Add a `lineCol` field with the zero-based start and end positions of
+    /// every pair, with `ch` in UTF-16 code units.
+    pub line_col: bool,
+    /// Add a `tag` field to pairs with a node tag.
+    pub tags: bool,
+}
+
+/// Renders every top-level node of `tree`, parsed from `input`, as
+/// pretty-printed JSON.
+///
+/// Pairs are `{ "pos", "rule", "inner" }` objects whose `inner` is the span
+/// text of leaves and a `{ "pos", "pairs" }` object otherwise. Rule names are
+/// printed bare, as for a derived parser's `Rule` enum.
+pub fn tree_to_json(tree: &ParseTree, input: &str, options: &JsonOptions) -> String {
+    let renderer = Renderer {
+        tree,
+        input,
+        lines: LineIndex::new(input),
+        options,
+    };
+
+    let mut json = String::new();
+    renderer
+        .pairs(&tree.roots().collect::<Vec<_>>())
+        .write(&mut json, 0);
+    json
+}
+
+struct Renderer<'a> {
+    tree: &'a ParseTree,
+    input: &'a str,
+    lines: LineIndex<'a>,
+    options: &'a JsonOptions,
+}
+
+impl Renderer<'_> {
+    fn pairs(&self, indices: &[usize]) -> Value {
+        let start = indices
+            .first()
+            .map_or(0, |&first| self.tree.nodes[first].start);
+        let end = indices.last().map_or(0, |&last| self.tree.nodes[last].end);
+
+        Value::Object(vec![
+            (
+                "pos",
+                Value::Array(vec![Value::Number(start), Value::Number(end)]),
+            ),
+            (
+                "pairs",
+                Value::Array(indices.iter().map(|&index| self.pair(index)).collect()),
+            ),
+        ])
+    }
+
+    fn pair(&self, index: usize) -> Value {
+        let node = &self.tree.nodes[index];
+        let mut fields = vec![
+            (
+                "pos",
+                Value::Array(vec![Value::Number(node.start), Value::Number(node.end)]),
+            ),
+            ("rule", Value::String(node.rule.clone())),
+        ];
+
+        if self.options.tags {
+            if let Some(tag) = &node.tag {
+                fields.push(("tag", Value::String(tag.clone())));
+            }
+        }
+        if self.options.line_col {
+            fields.push((
+                "lineCol",
+                Value::Array(vec![
+                    position(self.lines.position(node.start)),
+                    position(self.lines.position(node.end)),
+                ]),
+            ));
+        }
+
+        let inner = if node.children.is_empty() {
+            Value::String(self.input[node.start..node.end].to_owned())
+        } else {
+            self.pairs(&node.children)
+        };
+        fields.push(("inner", inner));
+
+        Value::Object(fields)
+    }
+}
+
+fn position(position: Position) -> Value {
+    Value::Object(vec![
+        ("line", Value::Number(position.line)),
+        ("ch", Value::Number(position.ch)),
+    ])
+}
+
+/// Just enough of a JSON value to print pairs the way `serde_json`'s pretty
+/// printer does.
+enum Value {
+    Number(usize),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(&'static str, Value)>),
+}
+
+impl Value {
+    fn write(&self, out: &mut String, depth: usize) {
+        match self {
+            Value::Number(number) => out.push_str(&number.to_string()),
+            Value::String(string) => write_string(out, string),
+            Value::Array(items) => {
+                write_block(out, depth, '[', ']', items, |out, item| {
+                    item.write(out, depth + 1)
+                });
+            }
+            Value::Object(fields) => {
+                write_block(out, depth, '{', '}', fields, |out, (key, value)| {
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, depth + 1);
+                });
+            }
+        }
+    }
+}
+
+fn write_block<T>(
+    out: &mut String,
+    depth: usize,
+    open: char,
+    close: char,
+    items: &[T],
+    mut write_item: impl FnMut(&mut String, &T),
+) {
+    out.push(open);
+
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth + 1));
+        write_item(out, item);
+    }
+
+    if !items.is_empty() {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    out.push(close);
+}
+
+/// Quotes `string`, escaping the same chars as `serde_json`.
+pub(crate) fn write_string(out: &mut String, string: &str) {
+    out.push('"');
+
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::fixture::{pair_list, pair_tree};
+
+    use super::*;
+
+    /// `json` without its layout, for inputs without whitespace.
+    fn compact(json: &str) -> String {
+        json.split_whitespace().collect()
+    }
+
+    #[test]
+    fn follows_pairs_to_json_layout() {
+        let expected = r#"{
+  "pos": [
+    0,
+    6
+  ],
+  "pairs": [
+    {
+      "pos": [
+        0,
+        6
+      ],
+      "rule": "pair",
+      "inner": {
+        "pos": [
+          0,
+          6
+        ],
+        "pairs": [
+          {
+            "pos": [
+              0,
+              2
+            ],
+            "rule": "key",
+            "inner": {
+              "pos": [
+                0,
+                2
+              ],
+              "pairs": [
+                {
+                  "pos": [
+                    0,
+                    2
+                  ],
+                  "rule": "ident",
+                  "inner": "ab"
+                }
+              ]
+            }
+          },
+          {
+            "pos": [
+              3,
+              6
+            ],
+            "rule": "value",
+            "inner": "\"\t\""
+          }
+        ]
+      }
+    }
+  ]
+}"#;
+
+        let input = "ab=\"\t\"";
+        let tree = pair_tree(r#""\"" ~ ANY ~ "\"""#, input);
+
+        assert_eq!(
+            tree_to_json(&tree, input, &JsonOptions::default()),
+            expected
+        );
+    }
+
+    #[test]
+    fn options_add_fields() {
+        let options = JsonOptions {
+            line_col: true,
+            tags: true,
+        };
+        let json = compact(&tree_to_json(
+            &pair_tree("ASCII_DIGIT+", "ab=12"),
+            "ab=12",
+            &options,
+        ));
+
+        assert!(json.contains(
+            r#""rule":"ident","tag":"name","lineCol":[{"line":0,"ch":0},{"line":0,"ch":2}],"inner":"ab""#
+        ));
+        assert_eq!(json.matches("\"tag\"").count(), 1);
+    }
+
+    #[test]
+    fn escapes_strings_like_serde_json() {
+        let input = "ab=\"\\\n\r\u{8}\u{c}\u{1}</é>";
+        let json = tree_to_json(&pair_tree("ANY+", input), input, &JsonOptions::default());
+
+        assert!(json.contains(r#""inner": "\"\\\n\r\b\f\u0001</é>""#));
+    }
+
+    #[test]
+    fn line_cols_count_utf16_units() {
+        let input = "ab=é😀ñ";
+        let options = JsonOptions {
+            line_col: true,
+            tags: false,
+        };
+        let json = compact(&tree_to_json(&pair_tree("ANY+", input), input, &options));
+
+        assert!(json.contains(
+            r#""pos":[3,11],"rule":"value","lineCol":[{"line":0,"ch":3},{"line":0,"ch":7}],"inner":"é😀ñ""#
+        ));
+    }
+
+    #[test]
+    fn lists_every_root_and_no_pairs_for_empty_trees() {
+        let input = "ab=1;cd=23";
+        let json = compact(&tree_to_json(
+            &pair_list("ASCII_DIGIT+", input),
+            input,
+            &JsonOptions::default(),
+        ));
+
+        assert!(json.starts_with(r#"{"pos":[0,10],"pairs":[{"pos":[0,4],"rule":"pair","#));
+        assert!(json.contains(r#"},{"pos":[5,10],"rule":"pair","#));
+        assert_eq!(
+            tree_to_json(&ParseTree::default(), "", &JsonOptions::default()),
+            "{\n  \"pos\": [\n    0,\n    0\n  ],\n  \"pairs\": []\n}"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Limits that stop runaway parses before they hang the page or overflow the
+//! wasm stack.
+//!
+//! Both are enforced by the `Vm`: the call limit caps how many rules a parse
+//! enters, and the depth limit how deeply grammar rules nest.
+
+use std::cell::{Cell, RefCell};
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_CALL_LIMIT: usize = 10_000_000;
+pub const DEFAULT_DEPTH_LIMIT: usize = 1_000;
+
+/// How many rule stack entries `LimitError::report` shows before eliding.
+const REPORTED_RULES: usize = 10;
+
+/// Per-playground parse limits. `None` disables a limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ParseLimits {
+    pub call_limit: Option<usize>,
+    pub depth_limit: Option<usize>,
+}
+
+impl Default for ParseLimits {
+    fn default() -> ParseLimits {
+        ParseLimits {
+            call_limit: Some(DEFAULT_CALL_LIMIT),
+            depth_limit: Some(DEFAULT_DEPTH_LIMIT),
+        }
+    }
+}
+
+impl ParseLimits {
+    pub fn unlimited() -> ParseLimits {
+        ParseLimits {
+            call_limit: None,
+            depth_limit: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitKind {
+    Call,
+    Depth,
+}
+
+/// A parse that was stopped by one of its `ParseLimits`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LimitError {
+    pub kind: LimitKind,
+    pub limit: usize,
+    /// The stack of rules being parsed when the limit was reached, outermost
+    /// first.
+    pub rules: Vec<String>,
+}
+
+impl LimitError {
+    /// The rule that was being parsed when the limit was reached.
+    pub fn rule(&self) -> Option<&str> {
+        self.rules.last().map(String::as_str)
+    }
+
+    /// Renders the error for the output pane.
+    pub fn report(&self) -> String {
+        let kind = match self.kind {
+            LimitKind::Call => "call",
+            LimitKind::Depth => "recursion depth",
+        };
+        let mut report = format!("{} limit of {} reached", kind, self.limit);
+
+        if let Some(rule) = self.rule() {
+            report.push_str(&format!(" in rule `{}`", rule));
+        }
+
+        if self.rules.len() > 1 {
+            let stack = if self.rules.len() > REPORTED_RULES {
+                let head = &self.rules[..3];
+                let tail = &self.rules[self.rules.len() - (REPORTED_RULES - 3)..];
+                format!("{} > ... > {}", head.join(" > "), tail.join(" > "))
+            } else {
+                self.rules.join(" > ")
+            };
+            report.push_str(&format!("\nrule stack: {}", stack));
+        }
+
+        report
+    }
+}
+
+/// Tracks the stack of grammar rules a `Vm` is parsing, and stops the parse
+/// once it goes past its `ParseLimits`.
+#[derive(Default)]
+pub struct RuleTracker {
+    limits: Cell<ParseLimits>,
+    calls: Cell<usize>,
+    frames: RefCell<Vec<String>>,
+    exceeded: Cell<Option<LimitKind>>,
+}
+
+impl RuleTracker {
+    /// Clears the rule stack and call count and applies `limits` before a
+    /// parse.
+    pub fn start(&self, limits: &ParseLimits) {
+        self.limits.set(*limits);
+        self.calls.set(0);
+        self.frames.borrow_mut().clear();
+        self.exceeded.set(None);
+    }
+
+    /// Records a call of `rule`, on the stack if it is defined by the
+    /// grammar rather than built in. Returns `false` once a limit is hit,
+    /// so every further rule fails and the parse winds down.
+    pub fn enter(&self, rule: &str, defined: bool) -> bool {
+        if self.exceeded.get().is_some() {
+            return false;
+        }
+
+        let limits = self.limits.get();
+        let calls = self.calls.get() + 1;
+        self.calls.set(calls);
+        let mut frames = self.frames.borrow_mut();
+        if defined {
+            frames.push(rule.to_owned());
+        }
+
+        if limits.call_limit.is_some_and(|limit| calls > limit) {
+            self.exceeded.set(Some(LimitKind::Call));
+        } else if limits.depth_limit.is_some_and(|limit| frames.len() > limit) {
+            self.exceeded.set(Some(LimitKind::Depth));
+        }
+
+        self.exceeded.get().is_none()
+    }
+
+    /// Pops the innermost defined rule once it returns. After a limit is
+    /// hit the stack is kept as it was, for `finish` to report.
+    pub fn exit(&self) {
+        if self.exceeded.get().is_none() {
+            self.frames.borrow_mut().pop();
+        }
+    }
+
+    /// Reports the limit the last parse hit, if any.
+    pub fn finish(&self) -> Option<LimitError> {
+        let limits = self.limits.get();
+        let (kind, limit) = match self.exceeded.get()? {
+            LimitKind::Call => (LimitKind::Call, limits.call_limit?),
+            LimitKind::Depth => (LimitKind::Depth, limits.depth_limit?),
+        };
+
+        Some(LimitError {
+            kind,
+            limit,
+            rules: self.frames.borrow().clone(),
+        })
+    }
+}
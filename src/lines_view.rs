@@ -0,0 +1,111 @@
+//! A scrolling view for long text outputs that only creates the lines in
+//! sight.
+
+use std::cell::Cell;
+use std::ops::Range;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{Document, Element};
+
+/// Height of one line, in pixels. It is set on the lines from here, so the
+/// scroll math always matches the layout.
+const LINE_HEIGHT: f64 = 20.0;
+/// Lines created above and below the visible ones, so scrolling doesn't show
+/// blank gaps before the next render.
+const OVERSCAN: usize = 50;
+/// Lines shown while the view has no height yet, e.g. before it is laid out.
+const FALLBACK_LINES: usize = 100;
+
+/// The `.editor-output-lines` container found under a playground's root, if
+/// its markup has one, with the elements the view adds to it.
+pub struct LinesView {
+    pub container: Element,
+    /// Gives the container the scroll height of every line.
+    spacer: Element,
+    /// Holds the created lines, moved to where they belong.
+    window: Element,
+    len: Cell<usize>,
+    shown: Cell<Option<(usize, usize)>>,
+}
+
+impl LinesView {
+    pub fn find(root: &Element) -> Option<LinesView> {
+        let container = root.query_selector(".editor-output-lines").unwrap_throw()?;
+        let document = container.owner_document().expect_throw("no document");
+        container.set_inner_html("");
+
+        let spacer = create(&document, "div", "lines-spacer");
+        let window = create(&document, "pre", "lines-window");
+        window
+            .set_attribute("style", &format!("line-height: {}px", LINE_HEIGHT))
+            .unwrap_throw();
+        container.append_child(&spacer).unwrap_throw();
+        container.append_child(&window).unwrap_throw();
+
+        Some(LinesView {
+            container,
+            spacer,
+            window,
+            len: Cell::new(0),
+            shown: Cell::new(None),
+        })
+    }
+
+    /// Sets the number of lines, and drops the ones shown so the next
+    /// `render` creates them again.
+    pub fn set_len(&self, len: usize) {
+        self.len.set(len);
+        self.shown.set(None);
+        self.spacer
+            .set_attribute("style", &format!("height: {}px", len as f64 * LINE_HEIGHT))
+            .unwrap_throw();
+        self.window.set_text_content(None);
+    }
+
+    /// Returns the lines that should be shown at the current scroll
+    /// position, or `None` if they already are.
+    pub fn lines_to_render(&self) -> Option<Range<usize>> {
+        let len = self.len.get();
+        let top = self.container.scroll_top() as f64;
+        let height = self.container.client_height() as f64;
+
+        let first = (top / LINE_HEIGHT) as usize;
+        let last = if height > 0.0 {
+            ((top + height) / LINE_HEIGHT).ceil() as usize
+        } else {
+            first + FALLBACK_LINES
+        };
+        let range = first.saturating_sub(OVERSCAN).min(len)..(last + OVERSCAN).min(len);
+
+        // Keep the shown lines while the visible ones are among them.
+        if let Some((start, end)) = self.shown.get() {
+            if start <= first.min(len) && last.min(len) <= end {
+                return None;
+            }
+        }
+
+        Some(range)
+    }
+
+    /// Shows `lines`, the lines from `first` on.
+    pub fn render(&self, first: usize, lines: &[String]) {
+        self.shown.set(Some((first, first + lines.len())));
+        self.window
+            .set_attribute(
+                "style",
+                &format!(
+                    "line-height: {}px; top: {}px",
+                    LINE_HEIGHT,
+                    first as f64 * LINE_HEIGHT
+                ),
+            )
+            .unwrap_throw();
+        self.window.set_text_content(Some(&lines.join("\n")));
+    }
+}
+
+fn create(document: &Document, name: &str, class: &str) -> Element {
+    let element = document.create_element(name).unwrap_throw();
+    element.set_class_name(class);
+    element
+}
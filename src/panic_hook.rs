@@ -0,0 +1,91 @@
+//! Records panics so the page can report them and recover.
+//!
+//! Panics abort the wasm instance mid-call, so the JS side only sees an
+//! `unreachable` trap. The hook keeps the message and location for
+//! `take_panic` to hand over once the trap has been caught.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{self, PanicHookInfo};
+use std::sync::Once;
+
+use serde::Serialize;
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PanicReport {
+    pub message: String,
+    /// `file:line:column` of the panic, if known.
+    pub location: Option<String>,
+}
+
+impl PanicReport {
+    fn new(info: &PanicHookInfo) -> PanicReport {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown panic"));
+
+        PanicReport {
+            message,
+            location: info.location().map(ToString::to_string),
+        }
+    }
+}
+
+impl fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "panicked at {}: {}", location, self.message),
+            None => write!(f, "panicked: {}", self.message),
+        }
+    }
+}
+
+/// Installs the recording hook in front of the current one. Safe to call more
+/// than once.
+pub fn install() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            let report = PanicReport::new(info);
+
+            #[cfg(target_arch = "wasm32")]
+            web_sys::console::error_1(&report.to_string().into());
+
+            LAST_PANIC.with(|last| *last.borrow_mut() = Some(report));
+            previous(info);
+        }));
+    });
+}
+
+/// Returns and clears the last recorded panic.
+pub fn take_panic() -> Option<PanicReport> {
+    LAST_PANIC.with(|last| last.borrow_mut().take())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panics_are_recorded_once() {
+        install();
+
+        let result = panic::catch_unwind(|| panic!("no VM for {}", "grammar"));
+
+        assert!(result.is_err());
+        let report = take_panic().unwrap();
+        assert_eq!(report.message, "no VM for grammar");
+        assert!(report.location.unwrap().starts_with("src/panic_hook.rs:"));
+        assert_eq!(take_panic(), None);
+    }
+}
@@ -0,0 +1,1119 @@
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use js_sys::{Function, Promise};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AbortSignal, AddEventListenerOptions, Element, Event, EventTarget};
+use web_sys::{HtmlOptionElement, HtmlSelectElement, HtmlTextAreaElement};
+
+use crate::debounce::Debounce;
+use crate::diagnostic::Diagnostic;
+use crate::export::{self, ExportFormat, ExportOptions};
+use crate::grammar::{CachedGrammar, GrammarCache, ParseOutcome};
+use crate::graph;
+use crate::highlight::InputHighlight;
+use crate::json::{self, JsonOptions};
+use crate::limits::ParseLimits;
+use crate::lines_view::LinesView;
+use crate::options_from_js;
+use crate::position::utf16_to_byte;
+use crate::printer::{self, PrinterOptions, TreeLines};
+use crate::session::definitions_to_value;
+use crate::stats::{now_ms, ParseStats};
+use crate::tree_view::{RenderedTree, TreeView};
+
+/// One editor on the page: the grammar's compiled VM, the input pane, the
+/// rule selector and the output pane found under a root element.
+#[wasm_bindgen]
+pub struct Playground {
+    inner: Rc<Inner>,
+    listeners: Vec<Listener>,
+}
+
+struct Inner {
+    root: Element,
+    input: HtmlTextAreaElement,
+    select: HtmlSelectElement,
+    output: HtmlTextAreaElement,
+    status: Option<Element>,
+    mode_select: Option<HtmlSelectElement>,
+    tree_view: Option<TreeView>,
+    lines_view: Option<LinesView>,
+    graph: Option<Element>,
+    stats_panel: Option<Element>,
+    highlight: Option<InputHighlight>,
+    /// Detaches the playground from the page without calling into it, once
+    /// a panic has left it unusable.
+    signal: Option<AbortSignal>,
+    on_timer: Closure<dyn Fn()>,
+    on_filter_timer: Closure<dyn Fn()>,
+    state: RefCell<State>,
+}
+
+#[derive(Default)]
+struct State {
+    cache: GrammarCache,
+    grammar: Option<CachedGrammar>,
+    /// Called with a `RuleDefinition` when a rule name is clicked.
+    definition_handler: Option<Function>,
+    source: String,
+    parser: Option<Function>,
+    limits: ParseLimits,
+    debounce: Debounce,
+    printer: PrinterOptions,
+    json: JsonOptions,
+    export: ExportOptions,
+    last_selection: Option<String>,
+    mode: OutputMode,
+    /// The last successful parse, kept for the tree view.
+    tree: Option<RenderedTree>,
+    /// The text output, when it is too long for the textarea and is shown by
+    /// the `LinesView` instead.
+    lines: Option<OutputLines>,
+    /// The pending debounce timer, if an input edit is waiting to be parsed.
+    timer: Option<Timer>,
+    /// The pending timer of a tree filter edit.
+    filter_timer: Option<Timer>,
+    /// How long the last parse took to come back, worker round trip
+    /// included, for `Debounce::delay`.
+    last_parse_ms: Option<f64>,
+    /// The numbers of the last successful parse.
+    stats: Option<ParseStats>,
+    /// Counts input edits, so results for older inputs leave the output
+    /// marked stale.
+    generation: u64,
+}
+
+/// How the output pane shows a successful parse.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum OutputMode {
+    #[default]
+    Text,
+    Json,
+    Export(ExportFormat),
+    Dot,
+    Svg,
+    Tree,
+}
+
+impl OutputMode {
+    /// The file name and media type the output is downloaded as. The tree
+    /// view has no text form, so it downloads as JSON.
+    fn file(self) -> (&'static str, &'static str) {
+        match self {
+            OutputMode::Text => ("parse.txt", "text/plain"),
+            OutputMode::Json | OutputMode::Tree => ("parse.json", "application/json"),
+            OutputMode::Export(ExportFormat::SExpr) => ("parse.sexp", "text/plain"),
+            OutputMode::Export(ExportFormat::Xml) => ("parse.xml", "application/xml"),
+            OutputMode::Export(ExportFormat::Yaml) => ("parse.yaml", "application/yaml"),
+            OutputMode::Dot => ("parse.dot", "text/vnd.graphviz"),
+            OutputMode::Svg => ("parse.svg", "image/svg+xml"),
+        }
+    }
+}
+
+/// How long the tree filter waits for typing to pause before applying.
+const FILTER_DELAY_MS: i32 = 150;
+
+/// Text outputs with more lines than this are shown by the `LinesView`,
+/// which only creates the lines in sight.
+const LAZY_LINES: usize = 2_000;
+
+/// The lines of a long text output.
+enum OutputLines {
+    /// The text printer's output, formatted a screenful at a time.
+    Tree(TreeLines),
+    /// Any other output, rendered whole up front, with the byte offset of
+    /// every line.
+    Text { text: String, starts: Vec<usize> },
+}
+
+impl OutputLines {
+    fn len(&self) -> usize {
+        match self {
+            OutputLines::Tree(lines) => lines.len(),
+            OutputLines::Text { starts, .. } => starts.len(),
+        }
+    }
+}
+
+/// The output of the last parse in the selected mode, as handed to JS for
+/// copying or downloading.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedOutput {
+    text: String,
+    file_name: &'static str,
+    mime_type: &'static str,
+}
+
+struct Listener {
+    target: EventTarget,
+    event: &'static str,
+    closure: Closure<dyn Fn(Event)>,
+}
+
+/// A pending timeout, and the plain JS function that clears it when the
+/// playground's signal aborts.
+struct Timer {
+    handle: i32,
+    clear: Function,
+}
+
+#[wasm_bindgen]
+impl Playground {
+    /// Attaches a playground to the editor markup under `root`. Aborting
+    /// `signal` removes its listeners and timer without calling into the
+    /// module, for dropping a playground after a panic.
+    #[wasm_bindgen(constructor)]
+    pub fn new(root: Element, signal: Option<AbortSignal>) -> Playground {
+        let inner = Rc::new_cyclic(|weak: &Weak<Inner>| {
+            let weak = weak.clone();
+            let filter_weak = weak.clone();
+            let input = find(&root, ".editor-input-text");
+            let highlight = InputHighlight::find(&root, &input);
+            Inner {
+                input,
+                select: find(&root, ".editor-input-select"),
+                output: find(&root, ".editor-output"),
+                status: root.query_selector(".editor-output-status").unwrap_throw(),
+                mode_select: root
+                    .query_selector(".editor-output-mode")
+                    .unwrap_throw()
+                    .and_then(|select| select.dyn_into().ok()),
+                tree_view: TreeView::find(&root),
+                lines_view: LinesView::find(&root),
+                stats_panel: root.query_selector(".editor-output-stats").unwrap_throw(),
+                graph: root.query_selector(".editor-output-graph").unwrap_throw(),
+                highlight,
+                signal,
+                on_timer: Closure::new(move || {
+                    if let Some(inner) = weak.upgrade() {
+                        inner.state.borrow_mut().timer = None;
+                        inner.parse_input();
+                    }
+                }),
+                on_filter_timer: Closure::new(move || {
+                    if let Some(inner) = filter_weak.upgrade() {
+                        inner.state.borrow_mut().filter_timer = None;
+                        inner.apply_filter();
+                    }
+                }),
+                root,
+                state: RefCell::default(),
+            }
+        });
+
+        let input: EventTarget = inner.input.clone().into();
+        let mut listeners = vec![
+            Listener::new(&inner, input.clone(), "input", |inner, _| {
+                inner.clear_highlight();
+                inner.schedule_parse();
+            }),
+            Listener::new(&inner, inner.select.clone().into(), "change", |inner, _| {
+                inner.state.borrow_mut().last_selection = inner.selected_option();
+                inner.parse_input();
+            }),
+            Listener::new(&inner, input.clone(), "scroll", |inner, _| {
+                if let Some(highlight) = &inner.highlight {
+                    highlight.sync_scroll();
+                }
+            }),
+        ];
+        for event in ["click", "keyup", "select"] {
+            listeners.push(Listener::new(&inner, input.clone(), event, |inner, _| {
+                inner.reveal_cursor();
+            }));
+        }
+
+        if let Some(select) = &inner.mode_select {
+            listeners.push(Listener::new(
+                &inner,
+                select.clone().into(),
+                "change",
+                |inner, _| {
+                    inner.update_mode();
+                },
+            ));
+        }
+        if let Some(view) = &inner.lines_view {
+            listeners.push(Listener::new(
+                &inner,
+                view.container.clone().into(),
+                "scroll",
+                |inner, _| {
+                    inner.render_lines();
+                },
+            ));
+        }
+        if let Some(view) = &inner.tree_view {
+            let container: EventTarget = view.container.clone().into();
+            listeners.push(Listener::new(
+                &inner,
+                container.clone(),
+                "click",
+                |inner, event| {
+                    if !inner.jump_to_rule(event) {
+                        inner.select_node(event);
+                    }
+                },
+            ));
+            if let Some(breadcrumb) = &view.breadcrumb {
+                listeners.push(Listener::new(
+                    &inner,
+                    breadcrumb.clone().into(),
+                    "click",
+                    |inner, event| {
+                        inner.jump_to_rule(event);
+                    },
+                ));
+            }
+            listeners.push(Listener::new(
+                &inner,
+                container.clone(),
+                "mouseover",
+                |inner, event| {
+                    inner.hover_node(event);
+                },
+            ));
+            listeners.push(Listener::new(
+                &inner,
+                container.clone(),
+                "mouseleave",
+                |inner, _| {
+                    inner.highlight_selected();
+                },
+            ));
+            listeners.push(Listener::new(&inner, container, "scroll", |inner, _| {
+                if let (Some(view), Some(tree)) =
+                    (&inner.tree_view, &mut inner.state.borrow_mut().tree)
+                {
+                    view.scrolled(tree);
+                }
+            }));
+            if let Some(filter) = &view.filter {
+                listeners.push(Listener::new(
+                    &inner,
+                    filter.clone().into(),
+                    "input",
+                    |inner, _| {
+                        inner.schedule_filter();
+                    },
+                ));
+            }
+        }
+        inner.update_mode();
+
+        Playground { inner, listeners }
+    }
+
+    /// Compiles `grammar` and returns its diagnostics. The input is only
+    /// re-parsed if the grammar's optimized rules changed.
+    pub fn lint(&self, grammar: JsValue) -> JsValue {
+        let grammar = grammar.as_string().expect_throw("grammar is not a string");
+
+        serde_wasm_bindgen::to_value(&self.inner.compile_grammar(&grammar))
+            .expect_throw("could not serialize grammar results")
+    }
+
+    /// Sets the call and recursion depth limits for parsing the input, and
+    /// re-parses it. Returns why invalid limits were rejected, leaving the
+    /// previous ones in place, or `undefined`.
+    pub fn set_limits(&self, limits: JsValue) -> Option<String> {
+        match options_from_js(limits, "parse limits") {
+            Ok(limits) => self.inner.state.borrow_mut().limits = limits,
+            Err(error) => return Some(error),
+        }
+        self.inner.parse_input();
+
+        None
+    }
+
+    /// Returns where each rule of the current grammar is defined, as a list
+    /// of `{ name, from, to }`.
+    pub fn definitions(&self) -> JsValue {
+        let state = self.inner.state.borrow();
+        definitions_to_value(state.grammar.as_ref().map(|grammar| &*grammar.definitions))
+    }
+
+    /// Calls `handler` with a rule's `{ name, from, to }` definition when its
+    /// name is clicked in the tree view or breadcrumb.
+    pub fn set_definition_handler(&self, handler: Function) {
+        self.inner.state.borrow_mut().definition_handler = Some(handler);
+    }
+
+    /// Sets the text output's `PrinterOptions`, or the defaults for
+    /// `undefined`, and reprints the last parse. Returns why invalid options
+    /// were rejected, leaving the previous ones in place, or `undefined`.
+    pub fn set_printer(&self, options: JsValue) -> Option<String> {
+        match options_from_js(options, "printer options") {
+            Ok(options) => self.inner.state.borrow_mut().printer = options,
+            Err(error) => return Some(error),
+        }
+        self.inner.update_mode();
+
+        None
+    }
+
+    /// Sets the `JsonOptions` of the JSON output, or the defaults for
+    /// `undefined`, and reprints the last parse. Returns why invalid options
+    /// were rejected, or `undefined`.
+    pub fn set_json_options(&self, options: JsValue) -> Option<String> {
+        match options_from_js(options, "JSON options") {
+            Ok(options) => self.inner.state.borrow_mut().json = options,
+            Err(error) => return Some(error),
+        }
+        self.inner.update_mode();
+
+        None
+    }
+
+    /// Sets the `ExportOptions` of the S-expression, XML and YAML outputs, or
+    /// the defaults for `undefined`, and reprints the last parse. Returns why
+    /// invalid options were rejected, or `undefined`.
+    pub fn set_export_options(&self, options: JsValue) -> Option<String> {
+        match options_from_js(options, "export options") {
+            Ok(options) => self.inner.state.borrow_mut().export = options,
+            Err(error) => return Some(error),
+        }
+        self.inner.update_mode();
+
+        None
+    }
+
+    /// Returns the `ParseStats` of the last successful parse, or `undefined`
+    /// if the input didn't parse.
+    pub fn stats(&self) -> JsValue {
+        match &self.inner.state.borrow().stats {
+            Some(stats) => {
+                serde_wasm_bindgen::to_value(stats).expect_throw("could not serialize stats")
+            }
+            None => JsValue::UNDEFINED,
+        }
+    }
+
+    /// Returns the last successful parse rendered in the selected output
+    /// mode, as `{ text, fileName, mimeType }`, or `undefined` if the input
+    /// didn't parse.
+    pub fn export(&self) -> JsValue {
+        let state = self.inner.state.borrow();
+        let mode = match state.mode {
+            OutputMode::Tree => OutputMode::Json,
+            mode => mode,
+        };
+        let text = match render_output(&state, mode) {
+            Some(text) => text,
+            None => return JsValue::UNDEFINED,
+        };
+        let (file_name, mime_type) = state.mode.file();
+
+        serde_wasm_bindgen::to_value(&ExportedOutput {
+            text,
+            file_name,
+            mime_type,
+        })
+        .expect_throw("could not serialize output")
+    }
+
+    /// Sets the longest wait after an input edit before re-parsing, as a
+    /// `{ maxDelayMs }` object or `undefined` for the default. Returns why an
+    /// invalid wait was rejected, or `undefined`.
+    pub fn set_debounce(&self, debounce: JsValue) -> Option<String> {
+        match options_from_js(debounce, "debounce options") {
+            Ok(debounce) => self.inner.state.borrow_mut().debounce = debounce,
+            Err(error) => return Some(error),
+        }
+
+        None
+    }
+
+    /// Hands parsing off to `parser`, a `(grammar, rule, input, limits) =>
+    /// Promise<ParseOutcome>` function, typically backed by a Web Worker
+    /// running a `Session`. Outcomes with status `"cancelled"` are dropped,
+    /// since a newer request replaced them.
+    pub fn set_parser(&self, parser: Function) {
+        self.inner.state.borrow_mut().parser = Some(parser);
+    }
+
+    pub fn root(&self) -> Element {
+        self.inner.root.clone()
+    }
+}
+
+impl Drop for Playground {
+    fn drop(&mut self) {
+        // The timer callbacks die with the playground, so they must not fire.
+        if let Ok(mut state) = self.inner.state.try_borrow_mut() {
+            let timers = [state.timer.take(), state.filter_timer.take()];
+            for timer in timers.into_iter().flatten() {
+                self.inner.clear_timer(timer);
+            }
+        }
+
+        for listener in &self.listeners {
+            let _ = listener.target.remove_event_listener_with_callback(
+                listener.event,
+                listener.closure.as_ref().unchecked_ref(),
+            );
+        }
+    }
+}
+
+impl Listener {
+    fn new(
+        inner: &Rc<Inner>,
+        target: EventTarget,
+        event: &'static str,
+        handler: fn(&Rc<Inner>, &Event),
+    ) -> Listener {
+        let weak = Rc::downgrade(inner);
+        let closure = Closure::<dyn Fn(Event)>::new(move |event: Event| {
+            if let Some(inner) = weak.upgrade() {
+                handler(&inner, &event);
+            }
+        });
+
+        let options = AddEventListenerOptions::new();
+        if let Some(signal) = &inner.signal {
+            options.set_signal(signal);
+        }
+        target
+            .add_event_listener_with_callback_and_add_event_listener_options(
+                event,
+                closure.as_ref().unchecked_ref(),
+                &options,
+            )
+            .unwrap_throw();
+
+        Listener {
+            target,
+            event,
+            closure,
+        }
+    }
+}
+
+impl Inner {
+    /// Marks the output stale and parses the input once the debounce delay
+    /// passes, replacing any parse already waiting.
+    fn schedule_parse(self: &Rc<Self>) {
+        let mut state = self.state.borrow_mut();
+        state.generation += 1;
+        self.set_stale(true);
+
+        if let Some(timer) = state.timer.take() {
+            self.clear_timer(timer);
+        }
+
+        let delay = state.debounce.delay(state.last_parse_ms);
+        if delay == 0 {
+            drop(state);
+            return self.parse_input();
+        }
+
+        state.timer = Some(self.start_timer(&self.on_timer, delay as i32));
+    }
+
+    /// Applies the tree filter once typing in it pauses.
+    fn schedule_filter(&self) {
+        let mut state = self.state.borrow_mut();
+        if let Some(timer) = state.filter_timer.take() {
+            self.clear_timer(timer);
+        }
+
+        state.filter_timer = Some(self.start_timer(&self.on_filter_timer, FILTER_DELAY_MS));
+    }
+
+    fn apply_filter(&self) {
+        if let (Some(view), Some(tree)) = (&self.tree_view, &mut self.state.borrow_mut().tree) {
+            view.apply_filter(tree);
+        }
+    }
+
+    /// Calls `callback` after `delay` milliseconds, unless the returned
+    /// timer is cleared or the playground's signal aborts first.
+    fn start_timer(&self, callback: &Closure<dyn Fn()>, delay: i32) -> Timer {
+        let window = window();
+        let handle = window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                callback.as_ref().unchecked_ref(),
+                delay,
+            )
+            .unwrap_throw();
+        let clear: Function = js_sys::Reflect::get(&window, &"clearTimeout".into())
+            .unwrap_throw()
+            .unchecked_into();
+        let clear: Function = clear.bind1(&window, &handle.into()).unchecked_into();
+        if let Some(signal) = &self.signal {
+            signal
+                .add_event_listener_with_callback("abort", &clear)
+                .unwrap_throw();
+        }
+
+        Timer { handle, clear }
+    }
+
+    fn clear_timer(&self, timer: Timer) {
+        window().clear_timeout_with_handle(timer.handle);
+        if let Some(signal) = &self.signal {
+            let _ = signal.remove_event_listener_with_callback("abort", &timer.clear);
+        }
+    }
+
+    fn parse_input(self: &Rc<Self>) {
+        let timer = self.state.borrow_mut().timer.take();
+        if let Some(timer) = timer {
+            self.clear_timer(timer);
+        }
+
+        // Nothing will replace the output, so it is no longer waiting on a
+        // parse either.
+        let rule = match self.selected_option() {
+            Some(rule) => rule,
+            None => return self.set_stale(false),
+        };
+        let state = self.state.borrow();
+        let grammar = match state.grammar.as_ref() {
+            Some(grammar) => grammar,
+            None => return self.set_stale(false),
+        };
+        let text = self.input.value();
+        let generation = state.generation;
+        let started = now_ms();
+
+        let parser = match state.parser.clone() {
+            Some(parser) => parser,
+            None => {
+                let outcome = grammar.run(&rule, &text, &state.limits);
+                drop(state);
+                self.state.borrow_mut().last_parse_ms = Some(now_ms() - started);
+                self.set_stale(false);
+                return self.render(outcome, text, generation);
+            }
+        };
+        let source = JsValue::from(state.source.as_str());
+        let limits = serde_wasm_bindgen::to_value(&state.limits).unwrap_throw();
+        // The parser may throw, which must not leave the state borrowed.
+        drop(state);
+
+        let promise: Promise = parser
+            .call4(
+                &JsValue::NULL,
+                &source,
+                &rule.into(),
+                &text.as_str().into(),
+                &limits,
+            )
+            .unwrap_throw()
+            .dyn_into()
+            .expect_throw("parser did not return a promise");
+        self.set_parsing(true);
+
+        let weak = Rc::downgrade(self);
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = JsFuture::from(promise).await;
+            let inner = match weak.upgrade() {
+                Some(inner) => inner,
+                None => return,
+            };
+
+            let outcome = match result {
+                Ok(value) => {
+                    serde_wasm_bindgen::from_value(value).expect_throw("invalid parse outcome")
+                }
+                Err(error) => {
+                    inner.set_parsing(false);
+                    inner.output.set_value(
+                        &error
+                            .as_string()
+                            .unwrap_or_else(|| String::from("parser failed")),
+                    );
+                    inner.mark_input_error(true);
+                    return;
+                }
+            };
+            if outcome == ParseOutcome::Cancelled {
+                return;
+            }
+
+            let current = {
+                let mut state = inner.state.borrow_mut();
+                state.last_parse_ms = Some(now_ms() - started);
+                state.generation == generation
+            };
+            inner.set_parsing(false);
+            inner.set_stale(!current);
+            inner.render(outcome, text, generation);
+        });
+    }
+
+    fn render(&self, outcome: ParseOutcome, input: String, generation: u64) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.tree = None;
+            state.stats = None;
+        }
+        self.clear_highlight();
+
+        match outcome {
+            ParseOutcome::Ok {
+                tree,
+                compile_ms,
+                parse_ms,
+            } => {
+                let mut state = self.state.borrow_mut();
+                state.stats = Some(ParseStats {
+                    compile_ms,
+                    grammar_cached: compile_ms.is_none(),
+                    parse_ms: Some(parse_ms),
+                    ..ParseStats::new(&tree, &input)
+                });
+                state.tree = Some(RenderedTree::new(tree, input, generation));
+                drop(state);
+                self.mark_input_error(false);
+            }
+            ParseOutcome::Error { diagnostic } => {
+                self.output.set_value(&diagnostic.report());
+                self.mark_input_error(true);
+            }
+            ParseOutcome::Limit { error } => {
+                self.output.set_value(&error.report());
+                self.mark_input_error(true);
+            }
+            ParseOutcome::Cancelled => {}
+        }
+
+        self.show_stats();
+        self.update_mode();
+    }
+
+    /// Fills the stats panel with the numbers of the last parse, or hides it
+    /// if the input didn't parse.
+    fn show_stats(&self) {
+        let panel = match &self.stats_panel {
+            Some(panel) => panel,
+            None => return,
+        };
+        let state = self.state.borrow();
+        let stats = match &state.stats {
+            Some(stats) => stats,
+            None => {
+                panel.set_attribute("hidden", "").unwrap_throw();
+                return;
+            }
+        };
+        let document = panel.owner_document().expect_throw("no document");
+        let element = |name: &str, text: &str| {
+            let element = document.create_element(name).unwrap_throw();
+            element.set_text_content(Some(text));
+            element
+        };
+
+        panel.set_inner_html("");
+        panel
+            .append_child(&element("summary", &stats.summary()))
+            .unwrap_throw();
+        let table = document.create_element("table").unwrap_throw();
+        for count in &stats.rule_counts {
+            let row = document.create_element("tr").unwrap_throw();
+            row.append_child(&element("td", &count.rule)).unwrap_throw();
+            row.append_child(&element("td", &count.count.to_string()))
+                .unwrap_throw();
+            table.append_child(&row).unwrap_throw();
+        }
+        panel.append_child(&table).unwrap_throw();
+        panel.remove_attribute("hidden").unwrap_throw();
+    }
+
+    /// Prints the last parse into the text output in the selected format,
+    /// or hands it to the `LinesView` if it is long. The tree view draws its
+    /// own, so nothing is printed for it.
+    ///
+    /// Only the text mode is printed lazily, from the retained tree. JSON,
+    /// S-expressions, XML, YAML and DOT are still rendered whole into one
+    /// `String`; for long ones the `LinesView` only saves creating every
+    /// line in the DOM.
+    fn print_tree(&self) {
+        let mut state = self.state.borrow_mut();
+        state.lines = None;
+        let rendered = match &state.tree {
+            Some(rendered) => rendered,
+            None => return,
+        };
+
+        let (output, lines) = match state.mode {
+            OutputMode::Text => {
+                let lines = TreeLines::new(&rendered.tree, &state.printer);
+                if self.lines_view.is_some() && lines.len() > LAZY_LINES {
+                    (String::new(), Some(OutputLines::Tree(lines)))
+                } else {
+                    let range = 0..lines.len();
+                    let text = lines.lines(&rendered.tree, &rendered.input, &state.printer, range);
+                    (text.join("\n"), None)
+                }
+            }
+            OutputMode::Tree => return,
+            mode => {
+                let output = render_output(&state, mode).unwrap_throw();
+                if let (Some(graph), OutputMode::Svg) = (&self.graph, mode) {
+                    graph.set_inner_html(&output);
+                    return;
+                }
+
+                let starts: Vec<_> = Some(0)
+                    .into_iter()
+                    .chain(output.match_indices('\n').map(|(i, _)| i + 1))
+                    .collect();
+                if self.lines_view.is_some() && starts.len() > LAZY_LINES {
+                    let lines = OutputLines::Text {
+                        text: output,
+                        starts,
+                    };
+                    (String::new(), Some(lines))
+                } else {
+                    (output, None)
+                }
+            }
+        };
+
+        self.output.set_value(&output);
+        if let (Some(view), Some(lines)) = (&self.lines_view, &lines) {
+            view.set_len(lines.len());
+        }
+        state.lines = lines;
+    }
+
+    /// Creates the lines of a long output that are in sight.
+    fn render_lines(&self) {
+        let view = match &self.lines_view {
+            Some(view) => view,
+            None => return,
+        };
+        let state = self.state.borrow();
+        let (lines, rendered) = match (&state.lines, &state.tree) {
+            (Some(lines), Some(rendered)) => (lines, rendered),
+            _ => return,
+        };
+        let range = match view.lines_to_render() {
+            Some(range) => range,
+            None => return,
+        };
+
+        let first = range.start;
+        let text = match lines {
+            OutputLines::Tree(lines) => {
+                lines.lines(&rendered.tree, &rendered.input, &state.printer, range)
+            }
+            OutputLines::Text { text, starts } => range
+                .map(|i| {
+                    let end = starts.get(i + 1).map_or(text.len(), |&next| next - 1);
+                    text[starts[i]..end].to_owned()
+                })
+                .collect(),
+        };
+        view.render(first, &text);
+    }
+
+    /// Shows the tree view if it is selected and there is a tree to show,
+    /// and the text output otherwise, so errors are always visible.
+    fn update_mode(&self) {
+        if let Some(select) = &self.mode_select {
+            self.state.borrow_mut().mode = match select.value().as_str() {
+                "json" => OutputMode::Json,
+                "sexpr" => OutputMode::Export(ExportFormat::SExpr),
+                "xml" => OutputMode::Export(ExportFormat::Xml),
+                "yaml" => OutputMode::Export(ExportFormat::Yaml),
+                "dot" => OutputMode::Dot,
+                "svg" => OutputMode::Svg,
+                "tree" => OutputMode::Tree,
+                _ => OutputMode::Text,
+            };
+        }
+        self.print_tree();
+
+        let mut state = self.state.borrow_mut();
+        let parsed = state.tree.is_some();
+        let show_tree = parsed && state.mode == OutputMode::Tree && self.tree_view.is_some();
+        let show_graph = parsed && state.mode == OutputMode::Svg && self.graph.is_some();
+        let show_lines = state.lines.is_some();
+
+        if let Some(view) = &self.tree_view {
+            match state.tree.as_mut() {
+                Some(tree) if show_tree => view.show(tree),
+                Some(_) => {}
+                None => view.clear(),
+            }
+            view.container
+                .toggle_attribute_with_force("hidden", !show_tree)
+                .unwrap_throw();
+        }
+        if let Some(graph) = &self.graph {
+            if !parsed {
+                graph.set_inner_html("");
+            }
+            graph
+                .toggle_attribute_with_force("hidden", !show_graph)
+                .unwrap_throw();
+        }
+        if let Some(view) = &self.lines_view {
+            view.container
+                .toggle_attribute_with_force("hidden", !show_lines)
+                .unwrap_throw();
+        }
+        self.output
+            .toggle_attribute_with_force("hidden", show_tree || show_graph || show_lines)
+            .unwrap_throw();
+
+        drop(state);
+        self.render_lines();
+    }
+
+    fn select_node(&self, event: &Event) {
+        let view = match &self.tree_view {
+            Some(view) => view,
+            None => return,
+        };
+        let index = match view.node_at(event) {
+            Some(index) => index,
+            None => return,
+        };
+
+        if let Some(tree) = self.state.borrow_mut().tree.as_mut() {
+            view.select(tree, index, false);
+        }
+        self.highlight_node(index);
+    }
+
+    /// Hands the definition of the rule name clicked in `event` to the
+    /// definition handler. Returns whether there was one to hand over.
+    fn jump_to_rule(&self, event: &Event) -> bool {
+        let rule = match self.tree_view.as_ref().and_then(|view| view.rule_at(event)) {
+            Some(rule) => rule,
+            None => return false,
+        };
+        let state = self.state.borrow();
+        let definition = state
+            .grammar
+            .iter()
+            .flat_map(|grammar| grammar.definitions.iter())
+            .find(|definition| definition.name == rule);
+        let (handler, definition) = match (&state.definition_handler, definition) {
+            (Some(handler), Some(definition)) => (
+                handler.clone(),
+                serde_wasm_bindgen::to_value(definition).unwrap_throw(),
+            ),
+            _ => return false,
+        };
+        drop(state);
+
+        // Keep the click from also toggling the node.
+        event.prevent_default();
+        handler.call1(&JsValue::NULL, &definition).unwrap_throw();
+
+        true
+    }
+
+    fn hover_node(&self, event: &Event) {
+        if let Some(index) = self.tree_view.as_ref().and_then(|view| view.node_at(event)) {
+            self.highlight_node(index);
+        }
+    }
+
+    /// Selects the deepest pair around the input's cursor, if the shown
+    /// tree was parsed from the current input.
+    fn reveal_cursor(&self) {
+        let view = match &self.tree_view {
+            Some(view) => view,
+            None => return,
+        };
+        let cursor = match self.input.selection_start().ok().flatten() {
+            Some(cursor) => cursor as usize,
+            None => return,
+        };
+        let mut state = self.state.borrow_mut();
+        let generation = state.generation;
+        let tree = match state.tree.as_mut() {
+            Some(tree) if tree.generation == generation => tree,
+            _ => return,
+        };
+
+        let index =
+            match utf16_to_byte(&tree.input, cursor).and_then(|byte| tree.tree.node_at(byte)) {
+                Some(index) => index,
+                None => return,
+            };
+        view.select(tree, index, true);
+        drop(state);
+
+        self.highlight_node(index);
+    }
+
+    /// Goes back to highlighting the selected node once the pointer leaves
+    /// the tree.
+    fn highlight_selected(&self) {
+        let selected = self
+            .state
+            .borrow()
+            .tree
+            .as_ref()
+            .and_then(|tree| tree.selected());
+
+        match selected {
+            Some(index) => self.highlight_node(index),
+            None => self.clear_highlight(),
+        }
+    }
+
+    fn highlight_node(&self, index: usize) {
+        let highlight = match &self.highlight {
+            Some(highlight) => highlight,
+            None => return,
+        };
+        let state = self.state.borrow();
+        // Spans of an older input would land on the wrong text.
+        let tree = match &state.tree {
+            Some(tree) if tree.generation == state.generation => tree,
+            _ => return highlight.clear(),
+        };
+
+        let node = &tree.tree.nodes[index];
+        highlight.show(tree.generation, &tree.input, node.start, node.end);
+    }
+
+    fn clear_highlight(&self) {
+        if let Some(highlight) = &self.highlight {
+            highlight.clear();
+        }
+    }
+
+    fn set_stale(&self, stale: bool) {
+        self.output
+            .class_list()
+            .toggle_with_force("editor-output-stale", stale)
+            .unwrap_throw();
+        if let Some(view) = &self.lines_view {
+            view.container
+                .class_list()
+                .toggle_with_force("editor-output-stale", stale)
+                .unwrap_throw();
+        }
+    }
+
+    fn set_parsing(&self, parsing: bool) {
+        if let Some(status) = &self.status {
+            status
+                .toggle_attribute_with_force("hidden", !parsing)
+                .unwrap_throw();
+        }
+    }
+
+    fn mark_input_error(&self, failed: bool) {
+        self.input
+            .class_list()
+            .toggle_with_force("editor-input-error", failed)
+            .unwrap_throw();
+    }
+
+    fn selected_option(&self) -> Option<String> {
+        self.select
+            .selected_options()
+            .item(0)?
+            .text_content()
+            .filter(|text| text != "...")
+    }
+
+    fn compile_grammar(self: &Rc<Self>, grammar: &str) -> Vec<Diagnostic> {
+        let result = self.state.borrow_mut().cache.compile(grammar);
+        let compiled = match result {
+            Ok(compiled) => compiled,
+            Err(errors) => {
+                self.state.borrow_mut().grammar = None;
+                self.add_rules_to_select(vec![]);
+                return errors;
+            }
+        };
+
+        let rule_names = compiled.grammar.rule_names().to_vec();
+        {
+            let mut state = self.state.borrow_mut();
+            let unchanged = state
+                .grammar
+                .as_ref()
+                .is_some_and(|current| Rc::ptr_eq(&current.grammar, &compiled.grammar));
+            // Even with the same rules, the definitions may have moved.
+            state.grammar = Some(compiled);
+            if unchanged {
+                return vec![];
+            }
+            state.source = grammar.to_owned();
+        }
+
+        self.add_rules_to_select(rule_names.iter().map(String::as_str).collect());
+
+        self.parse_input();
+
+        vec![]
+    }
+
+    fn add_rules_to_select(&self, mut rules: Vec<&str>) {
+        let select = &self.select;
+        let document = select.owner_document().expect_throw("no document");
+
+        while let Some(node) = select.first_child() {
+            select.remove_child(&node).unwrap_throw();
+        }
+
+        select.set_disabled(rules.is_empty());
+        if rules.is_empty() {
+            rules.push("...");
+        }
+
+        let state = self.state.borrow();
+        for rule in rules {
+            let option: HtmlOptionElement = document
+                .create_element("option")
+                .unwrap_throw()
+                .dyn_into()
+                .expect_throw("wrong element type");
+            option
+                .append_child(&document.create_text_node(rule))
+                .unwrap_throw();
+            select.append_child(&option).unwrap_throw();
+
+            if state.last_selection.as_deref() == Some(rule) {
+                option.set_selected(true);
+            }
+        }
+    }
+}
+
+/// Renders the last successful parse for `mode`, or returns `None` if the
+/// input didn't parse or `mode` is the tree view, which draws its own.
+fn render_output(state: &State, mode: OutputMode) -> Option<String> {
+    let rendered = state.tree.as_ref()?;
+    let (tree, input) = (&rendered.tree, rendered.input.as_str());
+
+    Some(match mode {
+        OutputMode::Text => printer::print_tree(tree, input, &state.printer),
+        OutputMode::Json => json::tree_to_json(tree, input, &state.json),
+        OutputMode::Export(format) => export::export_tree(tree, input, format, &state.export),
+        OutputMode::Dot => graph::tree_to_dot(tree, input),
+        OutputMode::Svg => graph::tree_to_svg(tree, input),
+        OutputMode::Tree => return None,
+    })
+}
+
+fn window() -> web_sys::Window {
+    web_sys::window().expect_throw("no window")
+}
+
+fn find<T: JsCast>(root: &Element, sel: &str) -> T {
+    root.query_selector(sel)
+        .unwrap_throw()
+        .expect_throw(&format!("no {} element", sel))
+        .dyn_into()
+        .expect_throw("wrong element type")
+}
@@ -0,0 +1,300 @@
+//! Conversions between the offset units used across the site: pest reports
+//! UTF-8 byte offsets, Rust iterates Unicode scalar values and CodeMirror and
+//! the DOM count UTF-16 code units.
+//!
+//! Lines are split the way CodeMirror splits them: on `\n`, `\r\n` and a lone
+//! `\r`.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// A zero-based editor position whose `ch` is measured in UTF-16 code units,
+/// laid out like `CodeMirror.Pos`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub ch: usize,
+}
+
+/// Returns the number of chars in `text` before byte offset `byte`.
+///
+/// Offsets past the end or inside a char are clamped to the previous char
+/// boundary.
+pub fn byte_to_char(text: &str, byte: usize) -> usize {
+    text[..floor_boundary(text, byte)].chars().count()
+}
+
+/// Returns the byte offset of the `ch`-th char of `text`, or `None` if `text`
+/// is shorter than that.
+pub fn char_to_byte(text: &str, ch: usize) -> Option<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain(Some(text.len()))
+        .nth(ch)
+}
+
+/// Returns the number of UTF-16 code units in `text` before byte offset
+/// `byte`.
+///
+/// Offsets past the end or inside a char are clamped to the previous char
+/// boundary.
+pub fn byte_to_utf16(text: &str, byte: usize) -> usize {
+    text[..floor_boundary(text, byte)]
+        .chars()
+        .map(char::len_utf16)
+        .sum()
+}
+
+/// Returns the byte offset at UTF-16 offset `utf16`, or `None` if `text` is
+/// shorter than that or the offset splits a surrogate pair.
+pub fn utf16_to_byte(text: &str, utf16: usize) -> Option<usize> {
+    let mut units = 0;
+
+    for (i, c) in text.char_indices() {
+        if units == utf16 {
+            return Some(i);
+        }
+        if units > utf16 {
+            return None;
+        }
+        units += c.len_utf16();
+    }
+
+    (units == utf16).then_some(text.len())
+}
+
+/// Bytes between two UTF-16 checkpoints of a `LineIndex`.
+const CHECKPOINT_BYTES: usize = 64;
+
+/// Start offsets of every line in a text, and how many UTF-16 code units
+/// come before every `CHECKPOINT_BYTES` or so, for mapping between byte
+/// offsets and positions in `O(log n)` however long the lines are.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+    /// `(byte, utf16)` pairs at char boundaries, starting with `(0, 0)`.
+    checkpoints: Vec<(usize, usize)>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> LineIndex<'a> {
+        let bytes = text.as_bytes();
+        let mut line_starts = vec![0];
+
+        for (i, &b) in bytes.iter().enumerate() {
+            match b {
+                b'\r' if bytes.get(i + 1) == Some(&b'\n') => {}
+                b'\r' | b'\n' => line_starts.push(i + 1),
+                _ => {}
+            }
+        }
+
+        let mut checkpoints = vec![(0, 0)];
+        let mut units = 0;
+        for (i, c) in text.char_indices() {
+            if i >= checkpoints[checkpoints.len() - 1].0 + CHECKPOINT_BYTES {
+                checkpoints.push((i, units));
+            }
+            units += c.len_utf16();
+        }
+
+        LineIndex {
+            text,
+            line_starts,
+            checkpoints,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Maps byte offset `byte` to a line and UTF-16 column.
+    ///
+    /// Offsets between a `\r` and its `\n` map to the end of the line.
+    pub fn position(&self, byte: usize) -> Position {
+        let byte = floor_boundary(self.text, byte);
+        let line = self.line_starts.partition_point(|&start| start <= byte) - 1;
+        let line_start = self.line_starts[line];
+
+        let mut end = byte;
+        if self.text[..byte].ends_with('\r') && self.text[byte..].starts_with('\n') {
+            end -= 1;
+        }
+
+        Position {
+            line,
+            ch: self.utf16_at(end) - self.utf16_at(line_start),
+        }
+    }
+
+    /// Returns the byte range of line `line` without its line ending, or
+    /// `None` if the text has fewer lines.
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let line_start = *self.line_starts.get(line)?;
+        let line_end = self
+            .line_starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.text.len());
+        let text = self.text[line_start..line_end].trim_end_matches(['\r', '\n']);
+
+        Some(line_start..line_start + text.len())
+    }
+
+    /// Maps `position` back to a byte offset, or `None` if it lies outside
+    /// the text or splits a surrogate pair.
+    pub fn offset(&self, position: Position) -> Option<usize> {
+        let range = self.line_range(position.line)?;
+        let target = self.utf16_at(range.start) + position.ch;
+
+        let checkpoint = self
+            .checkpoints
+            .partition_point(|&(_, units)| units <= target)
+            - 1;
+        let (start, units) = self.checkpoints[checkpoint];
+        if start > range.end {
+            return None;
+        }
+
+        utf16_to_byte(&self.text[start..range.end], target - units).map(|byte| start + byte)
+    }
+
+    /// Returns the number of UTF-16 code units before char boundary `byte`.
+    fn utf16_at(&self, byte: usize) -> usize {
+        let checkpoint = self
+            .checkpoints
+            .partition_point(|&(start, _)| start <= byte)
+            - 1;
+        let (start, units) = self.checkpoints[checkpoint];
+
+        units + byte_to_utf16(&self.text[start..], byte - start)
+    }
+}
+
+fn floor_boundary(text: &str, byte: usize) -> usize {
+    let mut byte = byte.min(text.len());
+
+    while !text.is_char_boundary(byte) {
+        byte -= 1;
+    }
+
+    byte
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "a\u{e9}\u{1F600}b";
+
+    #[test]
+    fn byte_and_char_offsets() {
+        assert_eq!(byte_to_char(TEXT, 0), 0);
+        assert_eq!(byte_to_char(TEXT, 1), 1);
+        assert_eq!(byte_to_char(TEXT, 3), 2);
+        assert_eq!(byte_to_char(TEXT, 7), 3);
+        assert_eq!(byte_to_char(TEXT, 8), 4);
+        assert_eq!(byte_to_char(TEXT, 5), 2);
+        assert_eq!(byte_to_char(TEXT, 100), 4);
+
+        assert_eq!(char_to_byte(TEXT, 2), Some(3));
+        assert_eq!(char_to_byte(TEXT, 3), Some(7));
+        assert_eq!(char_to_byte(TEXT, 4), Some(8));
+        assert_eq!(char_to_byte(TEXT, 5), None);
+    }
+
+    #[test]
+    fn byte_and_utf16_offsets() {
+        assert_eq!(byte_to_utf16(TEXT, 3), 2);
+        assert_eq!(byte_to_utf16(TEXT, 7), 4);
+        assert_eq!(byte_to_utf16(TEXT, 8), 5);
+
+        assert_eq!(utf16_to_byte(TEXT, 2), Some(3));
+        assert_eq!(utf16_to_byte(TEXT, 3), None);
+        assert_eq!(utf16_to_byte(TEXT, 4), Some(7));
+        assert_eq!(utf16_to_byte(TEXT, 5), Some(8));
+        assert_eq!(utf16_to_byte(TEXT, 6), None);
+    }
+
+    #[test]
+    fn positions_count_utf16_columns() {
+        let text = "x = { '\u{1F600}' }\ny = { x }";
+
+        let index = LineIndex::new(text);
+
+        assert_eq!(index.position(0), Position { line: 0, ch: 0 });
+        assert_eq!(index.position(11), Position { line: 0, ch: 9 });
+        assert_eq!(index.position(15), Position { line: 1, ch: 0 });
+        assert_eq!(index.position(21), Position { line: 1, ch: 6 });
+    }
+
+    #[test]
+    fn positions_split_lines_like_codemirror() {
+        let text = "a\r\nb\rc\nd";
+
+        let index = LineIndex::new(text);
+
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.position(1), Position { line: 0, ch: 1 });
+        assert_eq!(index.position(2), Position { line: 0, ch: 1 });
+        assert_eq!(index.position(3), Position { line: 1, ch: 0 });
+        assert_eq!(index.position(5), Position { line: 2, ch: 0 });
+        assert_eq!(index.position(7), Position { line: 3, ch: 0 });
+        assert_eq!(index.position(8), Position { line: 3, ch: 1 });
+
+        assert_eq!(index.line_range(0), Some(0..1));
+        assert_eq!(index.line_range(1), Some(3..4));
+        assert_eq!(index.line_range(2), Some(5..6));
+        assert_eq!(index.line_range(4), None);
+    }
+
+    #[test]
+    fn positions_on_long_lines() {
+        let text = "a\u{e9}\u{1F600}".repeat(10_000) + "\nb";
+        let index = LineIndex::new(&text);
+
+        for byte in (0..text.len())
+            .step_by(997)
+            .filter(|&byte| text.is_char_boundary(byte))
+        {
+            let position = index.position(byte);
+            assert_eq!(
+                position,
+                Position {
+                    line: 0,
+                    ch: byte_to_utf16(&text, byte)
+                }
+            );
+            assert_eq!(index.offset(position), Some(byte));
+        }
+        assert_eq!(index.position(text.len()), Position { line: 1, ch: 1 });
+        assert_eq!(
+            index.offset(Position {
+                line: 0,
+                ch: 40_001
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn offsets_round_trip_through_positions() {
+        let text = "a = { '\u{1F600}' }\r\nb = { a }\n";
+        let index = LineIndex::new(text);
+
+        for (byte, _) in text.char_indices().filter(|&(_, c)| c != '\n') {
+            assert_eq!(index.offset(index.position(byte)), Some(byte));
+        }
+
+        assert_eq!(index.offset(Position { line: 0, ch: 8 }), None);
+        assert_eq!(
+            index.offset(Position { line: 1, ch: 9 }),
+            Some(text.len() - 1)
+        );
+        assert_eq!(index.offset(Position { line: 1, ch: 10 }), None);
+        assert_eq!(index.offset(Position { line: 2, ch: 0 }), Some(text.len()));
+        assert_eq!(index.offset(Position { line: 3, ch: 0 }), None);
+    }
+}
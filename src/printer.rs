@@ -0,0 +1,328 @@
+//! Plain-text rendering of parse results for the output pane.
+
+use std::cell::OnceCell;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::position::LineIndex;
+use crate::tree::ParseTree;
+
+/// How spans are shown after rule names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SpanStyle {
+    #[default]
+    None,
+    /// Byte offsets, like `[3..5]`.
+    Bytes,
+    /// One-based lines and UTF-16 columns, like `[1:4-1:6]`.
+    LineCol,
+}
+
+/// Layout of the text output. The defaults print the classic layout.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PrinterOptions {
+    pub indent_width: usize,
+    /// Print a node with a single child on one line, as `parent > child`.
+    pub collapse_chains: bool,
+    pub spans: SpanStyle,
+    /// Cut span text longer than this many chars.
+    pub max_text_chars: Option<usize>,
+    /// Show the span text of nodes with children too, not only of leaves.
+    pub inner_text: bool,
+    /// Rules left out of the output; their children take their place.
+    pub hidden_rules: Vec<String>,
+}
+
+impl Default for PrinterOptions {
+    fn default() -> PrinterOptions {
+        PrinterOptions {
+            indent_width: 2,
+            collapse_chains: true,
+            spans: SpanStyle::None,
+            max_text_chars: None,
+            inner_text: false,
+            hidden_rules: vec![],
+        }
+    }
+}
+
+/// Prints every top-level node of `tree`, parsed from `input`, as an
+/// indented tree, one node per line.
+pub fn print_tree(tree: &ParseTree, input: &str, options: &PrinterOptions) -> String {
+    let lines = TreeLines::new(tree, options);
+    lines.lines(tree, input, options, 0..lines.len()).join("\n")
+}
+
+/// The lines of a printed tree, laid out up front but only formatted when
+/// asked for, so huge trees can be shown a screenful at a time.
+#[derive(Clone, Debug, Default)]
+pub struct TreeLines {
+    /// The indent level and the node each line starts with. With
+    /// `collapse_chains`, a line goes on to print the node's single-child
+    /// chain.
+    lines: Vec<(usize, usize)>,
+}
+
+impl TreeLines {
+    pub fn new(tree: &ParseTree, options: &PrinterOptions) -> TreeLines {
+        let printer = Printer {
+            tree,
+            input: "",
+            lines: OnceCell::new(),
+            options,
+        };
+        let mut lines = vec![];
+        let mut stack: Vec<_> = printer
+            .visible(tree.roots())
+            .into_iter()
+            .rev()
+            .map(|node| (0, node))
+            .collect();
+
+        while let Some((level, node)) = stack.pop() {
+            lines.push((level, node));
+
+            let last = *printer.chain(node).last().unwrap();
+            let children = printer.visible(tree.nodes[last].children.iter().copied());
+            stack.extend(children.into_iter().rev().map(|child| (level + 1, child)));
+        }
+
+        TreeLines { lines }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Formats the lines in `range`, which must come from the same `tree`
+    /// and `options` as `self`.
+    pub fn lines(
+        &self,
+        tree: &ParseTree,
+        input: &str,
+        options: &PrinterOptions,
+        range: Range<usize>,
+    ) -> Vec<String> {
+        let printer = Printer {
+            tree,
+            input,
+            lines: OnceCell::new(),
+            options,
+        };
+
+        self.lines[range]
+            .iter()
+            .map(|&(level, node)| printer.format_line(level, node))
+            .collect()
+    }
+}
+
+struct Printer<'a> {
+    tree: &'a ParseTree,
+    input: &'a str,
+    /// Built on first use, since only line:col spans need it.
+    lines: OnceCell<LineIndex<'a>>,
+    options: &'a PrinterOptions,
+}
+
+impl Printer<'_> {
+    /// Prints `node` and the chain of single children that follows it.
+    fn format_line(&self, level: usize, node: usize) -> String {
+        let mut line = " ".repeat(self.options.indent_width * level);
+        line.push_str("- ");
+
+        for (i, index) in self.chain(node).into_iter().enumerate() {
+            if i > 0 {
+                line.push_str(" > ");
+            }
+            let node = &self.tree.nodes[index];
+            if let Some(tag) = &node.tag {
+                line.push_str(&format!("(#{}) ", tag));
+            }
+            line.push_str(&node.rule);
+            line.push_str(&self.span(index));
+            if self.options.inner_text || self.visible(node.children.iter().copied()).is_empty() {
+                line.push_str(&format!(": {}", self.text(index)));
+            }
+        }
+
+        line
+    }
+
+    /// Returns `node` and, with `collapse_chains`, the single visible
+    /// children printed on its line.
+    fn chain(&self, node: usize) -> Vec<usize> {
+        let mut chain = vec![node];
+        if !self.options.collapse_chains {
+            return chain;
+        }
+
+        loop {
+            let last = &self.tree.nodes[*chain.last().unwrap()];
+            match self.visible(last.children.iter().copied())[..] {
+                [child] => chain.push(child),
+                _ => return chain,
+            }
+        }
+    }
+
+    /// Replaces hidden nodes among `nodes` with their visible descendants.
+    fn visible(&self, nodes: impl Iterator<Item = usize>) -> Vec<usize> {
+        let mut visible = vec![];
+
+        for index in nodes {
+            let node = &self.tree.nodes[index];
+            if self.options.hidden_rules.contains(&node.rule) {
+                visible.extend(self.visible(node.children.iter().copied()));
+            } else {
+                visible.push(index);
+            }
+        }
+
+        visible
+    }
+
+    fn span(&self, index: usize) -> String {
+        let node = &self.tree.nodes[index];
+
+        match self.options.spans {
+            SpanStyle::None => String::new(),
+            SpanStyle::Bytes => format!(" [{}..{}]", node.start, node.end),
+            SpanStyle::LineCol => {
+                let lines = self.lines.get_or_init(|| LineIndex::new(self.input));
+                let from = lines.position(node.start);
+                let to = lines.position(node.end);
+                format!(
+                    " [{}:{}-{}:{}]",
+                    from.line + 1,
+                    from.ch + 1,
+                    to.line + 1,
+                    to.ch + 1
+                )
+            }
+        }
+    }
+
+    fn text(&self, index: usize) -> String {
+        let node = &self.tree.nodes[index];
+        let text = &self.input[node.start..node.end];
+
+        match self
+            .options
+            .max_text_chars
+            .and_then(|max| text.char_indices().nth(max))
+        {
+            Some((end, _)) => format!("{:?}…", &text[..end]),
+            None => format!("{:?}", text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::fixture::{pair_list, pair_tree};
+
+    use super::*;
+
+    #[test]
+    fn prints_nested_pairs() {
+        assert_eq!(
+            print_tree(
+                &pair_tree("ASCII_DIGIT+", "ab=12"),
+                "ab=12",
+                &PrinterOptions::default()
+            ),
+            "- pair\n  - key > (#name) ident: \"ab\"\n  - value: \"12\""
+        );
+    }
+
+    #[test]
+    fn options_change_the_layout() {
+        let options = PrinterOptions {
+            indent_width: 4,
+            collapse_chains: false,
+            spans: SpanStyle::Bytes,
+            max_text_chars: Some(3),
+            inner_text: true,
+            hidden_rules: vec![],
+        };
+
+        assert_eq!(
+            print_tree(&pair_tree("ASCII_DIGIT+", "ab=12"), "ab=12", &options),
+            "- pair [0..5]: \"ab=\"…\n    - key [0..2]: \"ab\"\n        - (#name) ident [0..2]: \"ab\"\n    - value [3..5]: \"12\""
+        );
+    }
+
+    #[test]
+    fn lines_are_formatted_on_demand() {
+        let tree = pair_tree("ASCII_DIGIT+", "ab=12");
+        let options = PrinterOptions::default();
+        let lines = TreeLines::new(&tree, &options);
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines.lines(&tree, "ab=12", &options, 1..3),
+            ["  - key > (#name) ident: \"ab\"", "  - value: \"12\""]
+        );
+    }
+
+    #[test]
+    fn hidden_rules_promote_their_children() {
+        let options = PrinterOptions {
+            spans: SpanStyle::LineCol,
+            hidden_rules: vec![String::from("pair"), String::from("key")],
+            ..PrinterOptions::default()
+        };
+
+        assert_eq!(
+            print_tree(&pair_tree("ASCII_DIGIT+", "ab=12"), "ab=12", &options),
+            "- (#name) ident [1:1-1:3]: \"ab\"\n- value [1:4-1:6]: \"12\""
+        );
+    }
+
+    #[test]
+    fn text_is_escaped_and_cut_by_chars() {
+        let input = "ab=\"\\\n\t<&>'";
+        assert_eq!(
+            print_tree(&pair_tree("ANY+", input), input, &PrinterOptions::default()),
+            "- pair\n  - key > (#name) ident: \"ab\"\n  - value: \"\\\"\\\\\\n\\t<&>'\""
+        );
+
+        let input = "ab=é😀ñ";
+        let options = PrinterOptions {
+            spans: SpanStyle::LineCol,
+            max_text_chars: Some(2),
+            ..PrinterOptions::default()
+        };
+        assert_eq!(
+            print_tree(&pair_tree("ANY+", input), input, &options),
+            "- pair [1:1-1:8]\n  - key [1:1-1:3] > (#name) ident [1:1-1:3]: \"ab\"\n  - value [1:4-1:8]: \"é😀\"…"
+        );
+    }
+
+    #[test]
+    fn prints_every_root_and_nothing_for_empty_trees() {
+        let input = "ab=1;cd=23";
+
+        assert_eq!(
+            print_tree(
+                &pair_list("ASCII_DIGIT+", input),
+                input,
+                &PrinterOptions::default()
+            ),
+            "- pair\n  - key > (#name) ident: \"ab\"\n  - value: \"1\"\n- pair\n  - key > (#name) ident: \"cd\"\n  - value: \"23\""
+        );
+        assert_eq!(
+            print_tree(&ParseTree::default(), "", &PrinterOptions::default()),
+            ""
+        );
+        assert!(TreeLines::new(&ParseTree::default(), &PrinterOptions::default()).is_empty());
+    }
+}
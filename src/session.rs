@@ -0,0 +1,114 @@
+use pest::error::{Error, ErrorVariant};
+use pest::Position;
+use wasm_bindgen::prelude::*;
+
+use crate::diagnostic::InputDiagnostic;
+use crate::grammar::{CachedGrammar, GrammarCache, ParseOutcome, RuleDefinition};
+use crate::json::{self, JsonOptions};
+use crate::limits::ParseLimits;
+use crate::options_from_js;
+use crate::tree::ParseTree;
+
+/// A DOM-free compile-and-parse session, for hosting the parser in a Web
+/// Worker or under Node.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Session {
+    cache: GrammarCache,
+    grammar: Option<CachedGrammar>,
+    json: JsonOptions,
+    /// The tree and input of the last successful parse, for `to_json`.
+    last_parse: Option<(ParseTree, String)>,
+}
+
+#[wasm_bindgen]
+impl Session {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Compiles `grammar` for later `parse` calls and returns its
+    /// diagnostics.
+    pub fn compile(&mut self, grammar: &str) -> JsValue {
+        let diagnostics = match self.cache.compile(grammar) {
+            Ok(compiled) => {
+                self.grammar = Some(compiled);
+                vec![]
+            }
+            Err(diagnostics) => {
+                self.grammar = None;
+                diagnostics
+            }
+        };
+
+        serde_wasm_bindgen::to_value(&diagnostics).expect_throw("could not serialize diagnostics")
+    }
+
+    /// Returns where each rule of the last compiled grammar is defined, as
+    /// a list of `{ name, from, to }`.
+    pub fn definitions(&self) -> JsValue {
+        definitions_to_value(self.grammar.as_ref().map(|grammar| &*grammar.definitions))
+    }
+
+    /// Parses `input` with the last compiled grammar within `limits`, a
+    /// `ParseLimits` object or `undefined` for the defaults, and returns a
+    /// `ParseOutcome`. Without a grammar that compiled, or with invalid
+    /// limits, the outcome is an error at the start of the input.
+    pub fn parse(&mut self, rule: &str, input: &str, limits: JsValue) -> JsValue {
+        let outcome = match (
+            &self.grammar,
+            options_from_js::<ParseLimits>(limits, "parse limits"),
+        ) {
+            (_, Err(message)) => error_outcome(message, input),
+            (None, Ok(_)) => error_outcome(String::from("the grammar has not compiled"), input),
+            (Some(grammar), Ok(limits)) => grammar.run(rule, input, &limits),
+        };
+
+        let value = serde_wasm_bindgen::to_value(&outcome)
+            .expect_throw("could not serialize parse results");
+        self.last_parse = match outcome {
+            ParseOutcome::Ok { tree, .. } => Some((tree, input.to_owned())),
+            _ => None,
+        };
+
+        value
+    }
+
+    /// Sets the `JsonOptions` of `to_json`, or the defaults for `undefined`.
+    /// Returns why invalid options were rejected, leaving the previous ones
+    /// in place, or `undefined`.
+    pub fn set_json_options(&mut self, options: JsValue) -> Option<String> {
+        match options_from_js(options, "JSON options") {
+            Ok(options) => self.json = options,
+            Err(error) => return Some(error),
+        }
+
+        None
+    }
+
+    /// Returns the last parse as JSON in the layout of pest's
+    /// `Pairs::to_json`, or `undefined` if it failed.
+    pub fn to_json(&self) -> Option<String> {
+        let (tree, input) = self.last_parse.as_ref()?;
+
+        Some(json::tree_to_json(tree, input, &self.json))
+    }
+}
+
+/// An error outcome for a parse that could not start.
+fn error_outcome(message: String, input: &str) -> ParseOutcome {
+    let error = Error::new_from_pos(
+        ErrorVariant::CustomError { message },
+        Position::from_start(input),
+    );
+
+    ParseOutcome::Error {
+        diagnostic: InputDiagnostic::from_error(error, input),
+    }
+}
+
+pub(crate) fn definitions_to_value(definitions: Option<&[RuleDefinition]>) -> JsValue {
+    serde_wasm_bindgen::to_value(definitions.unwrap_or_default())
+        .expect_throw("could not serialize rule definitions")
+}
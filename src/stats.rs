@@ -0,0 +1,156 @@
+//! Summary numbers of a parse, for spotting grammars that nest too deeply or
+//! run slower than expected.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::tree::ParseTree;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseStats {
+    pub pairs: usize,
+    /// The most pairs on any path from a top-level pair down, so a lone
+    /// pair has depth 1.
+    pub max_depth: usize,
+    /// How many pairs each rule produced, most first.
+    pub rule_counts: Vec<RuleCount>,
+    /// Bytes up to the end of the last top-level pair.
+    pub bytes_consumed: usize,
+    pub input_bytes: usize,
+    /// Wall time of compiling the grammar, in milliseconds, if known.
+    pub compile_ms: Option<f64>,
+    /// Whether the grammar came out of a cache rather than being compiled.
+    pub grammar_cached: bool,
+    /// Wall time of the parse itself, in milliseconds, if known.
+    pub parse_ms: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuleCount {
+    pub rule: String,
+    pub count: usize,
+}
+
+/// Milliseconds on a monotonic clock: `performance.now()` on the page and in
+/// workers, or since the first call natively.
+pub fn now_ms() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        performance_now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::sync::OnceLock;
+        use std::time::Instant;
+
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+impl ParseStats {
+    /// Counts the pairs of `tree`, parsed from `input`. Timings are left
+    /// for the caller to fill in.
+    pub fn new(tree: &ParseTree, input: &str) -> ParseStats {
+        let mut depths = vec![0; tree.nodes.len()];
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+
+        // Parents come before their children, so their depth is known.
+        for (index, node) in tree.nodes.iter().enumerate() {
+            depths[index] = node.parent.map_or(0, |parent| depths[parent]) + 1;
+            *counts.entry(&node.rule).or_default() += 1;
+        }
+
+        let mut rule_counts: Vec<_> = counts
+            .into_iter()
+            .map(|(rule, count)| RuleCount {
+                rule: rule.to_owned(),
+                count,
+            })
+            .collect();
+        rule_counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.rule.cmp(&b.rule)));
+
+        ParseStats {
+            pairs: tree.nodes.len(),
+            max_depth: depths.into_iter().max().unwrap_or(0),
+            rule_counts,
+            bytes_consumed: tree.roots().last().map_or(0, |root| tree.nodes[root].end),
+            input_bytes: input.len(),
+            compile_ms: None,
+            grammar_cached: false,
+            parse_ms: None,
+        }
+    }
+
+    /// One line with the totals and timings, for the stats panel's heading.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} pairs, depth {}, {} of {} bytes",
+            self.pairs, self.max_depth, self.bytes_consumed, self.input_bytes
+        );
+
+        if let Some(ms) = self.compile_ms {
+            summary.push_str(&format!(", compiled in {:.1} ms", ms));
+        } else if self.grammar_cached {
+            summary.push_str(", grammar cached");
+        }
+        if let Some(ms) = self.parse_ms {
+            summary.push_str(&format!(", parsed in {:.1} ms", ms));
+        }
+
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar;
+
+    use super::*;
+
+    #[test]
+    fn counts_pairs_depth_and_rules() {
+        let grammar = grammar::compile(
+            "list = { item ~ (\",\" ~ item)* }\nitem = { digit+ }\ndigit = { ASCII_DIGIT }",
+        )
+        .unwrap();
+        let input = "1,23 ";
+        let tree = ParseTree::new(grammar.parse("list", input).unwrap());
+
+        let stats = ParseStats {
+            compile_ms: Some(2.0),
+            ..ParseStats::new(&tree, input)
+        };
+
+        assert_eq!(stats.pairs, 6);
+        assert_eq!(stats.max_depth, 3);
+        let counts: Vec<_> = stats
+            .rule_counts
+            .iter()
+            .map(|count| (count.rule.as_str(), count.count))
+            .collect();
+        assert_eq!(counts, [("digit", 3), ("item", 2), ("list", 1)]);
+        assert_eq!(
+            stats.summary(),
+            "6 pairs, depth 3, 4 of 5 bytes, compiled in 2.0 ms"
+        );
+
+        let cached = ParseStats {
+            grammar_cached: true,
+            ..ParseStats::new(&tree, input)
+        };
+        assert_eq!(
+            cached.summary(),
+            "6 pairs, depth 3, 4 of 5 bytes, grammar cached"
+        );
+    }
+}
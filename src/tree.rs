@@ -0,0 +1,201 @@
+//! An owned, serializable copy of a parse result, for views that outlive the
+//! `Pairs` or live on another thread than the parser.
+
+use pest::iterators::Pairs;
+use serde::{Deserialize, Serialize};
+
+/// The pairs of a parse in pre-order, so every node comes after its parent
+/// and before its children.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ParseTree {
+    pub nodes: Vec<TreeNode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TreeNode {
+    pub rule: String,
+    pub tag: Option<String>,
+    /// Byte range of the pair's span in the parsed input.
+    pub start: usize,
+    pub end: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+impl ParseTree {
+    pub fn new(pairs: Pairs<&str>) -> ParseTree {
+        let mut nodes: Vec<TreeNode> = vec![];
+        let mut stack = vec![(None, pairs)];
+
+        while let Some((parent, mut siblings)) = stack.pop() {
+            let pair = match siblings.next() {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let index = nodes.len();
+            let span = pair.as_span();
+
+            nodes.push(TreeNode {
+                rule: pair.as_rule().to_owned(),
+                tag: pair.as_node_tag().map(str::to_owned),
+                start: span.start(),
+                end: span.end(),
+                parent,
+                children: vec![],
+            });
+            if let Some(parent) = parent {
+                nodes[parent].children.push(index);
+            }
+
+            stack.push((parent, siblings));
+            stack.push((Some(index), pair.into_inner()));
+        }
+
+        ParseTree { nodes }
+    }
+
+    /// Indices of the top-level nodes.
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(index, _)| index)
+    }
+
+    /// Returns `index` and its ancestors, outermost first.
+    pub fn path(&self, index: usize) -> Vec<usize> {
+        let mut path = vec![index];
+
+        while let Some(parent) = self.nodes[*path.last().unwrap()].parent {
+            path.push(parent);
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Returns the deepest node whose span contains byte `offset`. A span
+    /// also counts as containing its end, so a cursor just past a pair still
+    /// finds it, but spans that start at `offset` win over ones that end
+    /// there.
+    pub fn node_at(&self, offset: usize) -> Option<usize> {
+        let contains = |&index: &usize| {
+            let node = &self.nodes[index];
+            node.start <= offset && offset <= node.end
+        };
+        let best = |candidates: &mut dyn Iterator<Item = usize>| {
+            let candidates: Vec<_> = candidates.filter(contains).collect();
+            candidates
+                .iter()
+                .copied()
+                .find(|&index| offset < self.nodes[index].end)
+                .or_else(|| candidates.last().copied())
+        };
+
+        let mut node = best(&mut self.roots())?;
+        while let Some(child) = best(&mut self.nodes[node].children.iter().copied()) {
+            node = child;
+        }
+
+        Some(node)
+    }
+
+    /// Marks the nodes whose rule name contains `query`, and their
+    /// ancestors, as visible. An empty query shows everything.
+    pub fn filter(&self, query: &str) -> Vec<bool> {
+        let mut visible = vec![query.is_empty(); self.nodes.len()];
+
+        if query.is_empty() {
+            return visible;
+        }
+
+        // Children come after their parents, so walking backwards settles
+        // every child before its parent.
+        for index in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[index];
+            if node.rule.contains(query) {
+                visible[index] = true;
+            }
+            if visible[index] {
+                if let Some(parent) = node.parent {
+                    visible[parent] = true;
+                }
+            }
+        }
+
+        visible
+    }
+}
+
+/// The `key=value` tree the renderers' tests share.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use crate::grammar;
+
+    use super::ParseTree;
+
+    /// Parses `input` as a `pair` of a `key`, whose `ident` is tagged
+    /// `#name`, and a `value` matching the expression `value`.
+    pub fn pair_tree(value: &str, input: &str) -> ParseTree {
+        parse(value, "pair", input)
+    }
+
+    /// Parses `input` as `;`-separated pairs, each one a root.
+    pub fn pair_list(value: &str, input: &str) -> ParseTree {
+        parse(value, "pairs", input)
+    }
+
+    fn parse(value: &str, rule: &str, input: &str) -> ParseTree {
+        let grammar = grammar::compile(&format!(
+            "pairs = _{{ pair ~ (\";\" ~ pair)* }}\npair = {{ key ~ \"=\" ~ value }}\nkey = {{ #name = ident }}\nident = {{ ASCII_ALPHA+ }}\nvalue = {{ {} }}",
+            value
+        ))
+        .unwrap();
+
+        ParseTree::new(grammar.parse(rule, input).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::pair_tree;
+    use super::*;
+
+    fn tree() -> ParseTree {
+        pair_tree("ASCII_DIGIT+", "ab=12")
+    }
+
+    #[test]
+    fn nodes_are_in_pre_order() {
+        let tree = tree();
+
+        let rules: Vec<_> = tree.nodes.iter().map(|node| node.rule.as_str()).collect();
+        assert_eq!(rules, ["pair", "key", "ident", "value"]);
+        assert_eq!(tree.roots().collect::<Vec<_>>(), [0]);
+        assert_eq!(tree.nodes[0].children, [1, 3]);
+        assert_eq!(tree.nodes[2].tag.as_deref(), Some("name"));
+        assert_eq!((tree.nodes[3].start, tree.nodes[3].end), (3, 5));
+        assert_eq!(tree.path(2), [0, 1, 2]);
+    }
+
+    #[test]
+    fn offsets_find_the_deepest_pair() {
+        let tree = tree();
+
+        assert_eq!(tree.node_at(0), Some(2));
+        assert_eq!(tree.node_at(2), Some(2));
+        assert_eq!(tree.node_at(3), Some(3));
+        assert_eq!(tree.node_at(5), Some(3));
+        assert_eq!(tree.node_at(6), None);
+    }
+
+    #[test]
+    fn filters_keep_ancestors() {
+        let tree = tree();
+
+        assert_eq!(tree.filter("ident"), [true, true, true, false]);
+        assert_eq!(tree.filter(""), [true; 4]);
+        assert_eq!(tree.filter("nothing"), [false; 4]);
+    }
+}
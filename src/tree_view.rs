@@ -0,0 +1,361 @@
+//! The collapsible parse-tree view of the output pane.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, Event, HtmlInputElement};
+
+use crate::tree::ParseTree;
+
+/// Span text longer than this is cut short in node labels.
+const LABEL_TEXT_CHARS: usize = 40;
+/// About how many nodes are created when a tree is first shown, expanding
+/// it breadth first. Deeper nodes stay collapsed and are only created when
+/// their parent is opened.
+const INITIAL_NODES: usize = 1_000;
+/// How many filter matches have their ancestors opened at a time. The rest
+/// stay collapsed until the tree is scrolled near its end or they are
+/// revealed.
+const OPENED_MATCHES: usize = 100;
+
+/// The tree container, rule filter and breadcrumb found under a playground's
+/// root, if its markup has them.
+pub struct TreeView {
+    pub container: Element,
+    pub filter: Option<HtmlInputElement>,
+    pub breadcrumb: Option<Element>,
+}
+
+/// A tree as currently shown, with the input it was parsed from.
+pub struct RenderedTree {
+    pub tree: ParseTree,
+    pub input: String,
+    /// The input generation `input` was taken at.
+    pub generation: u64,
+    /// The nodes created so far, or `None` before the tree is first shown.
+    built: Option<Built>,
+    selected: Option<usize>,
+    /// Which nodes the filter shows, or empty if it shows every node.
+    visible: Vec<bool>,
+    /// The filter matches whose ancestors weren't opened yet, in tree order.
+    unopened: VecDeque<usize>,
+}
+
+struct Built {
+    /// The element of every node created so far.
+    elements: Vec<Option<Element>>,
+    /// Where each node's children go: the `tree-children` div of inner
+    /// nodes.
+    child_lists: Vec<Option<Element>>,
+    /// Whether each node's children were created.
+    expanded: Vec<bool>,
+}
+
+impl RenderedTree {
+    pub fn new(tree: ParseTree, input: String, generation: u64) -> RenderedTree {
+        RenderedTree {
+            tree,
+            input,
+            generation,
+            built: None,
+            selected: None,
+            visible: vec![],
+            unopened: VecDeque::new(),
+        }
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+}
+
+impl TreeView {
+    pub fn find(root: &Element) -> Option<TreeView> {
+        let container = root.query_selector(".editor-output-tree").unwrap_throw()?;
+
+        Some(TreeView {
+            container,
+            filter: root
+                .query_selector(".editor-tree-filter")
+                .unwrap_throw()
+                .and_then(|filter| filter.dyn_into().ok()),
+            breadcrumb: root
+                .query_selector(".editor-tree-breadcrumb")
+                .unwrap_throw(),
+        })
+    }
+
+    pub fn clear(&self) {
+        self.container.set_inner_html("");
+        self.set_breadcrumb(&[]);
+    }
+
+    /// Builds the DOM for the top of `rendered` unless it was built
+    /// already, then applies the current filter.
+    pub fn show(&self, rendered: &mut RenderedTree) {
+        if rendered.built.is_none() {
+            self.clear();
+            let len = rendered.tree.nodes.len();
+            rendered.built = Some(Built {
+                elements: vec![None; len],
+                child_lists: vec![None; len],
+                expanded: vec![false; len],
+            });
+            rendered.selected = None;
+
+            let mut queue: VecDeque<_> = rendered.tree.roots().collect();
+            for &root in &queue {
+                self.build_node(rendered, root);
+            }
+            let mut count = queue.len();
+            while let Some(index) = queue.pop_front() {
+                let children = &rendered.tree.nodes[index].children;
+                if children.is_empty() {
+                    continue;
+                }
+                if count + children.len() > INITIAL_NODES {
+                    break;
+                }
+                count += children.len();
+                queue.extend(children);
+                self.expand(rendered, index, true);
+            }
+        }
+
+        self.apply_filter(rendered);
+    }
+
+    /// Hides the nodes that don't match the filter and have no descendant
+    /// that does, and opens the ancestors of the first `OPENED_MATCHES`
+    /// matches, creating them if needed, so those matches are in sight.
+    pub fn apply_filter(&self, rendered: &mut RenderedTree) {
+        let query = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.value())
+            .unwrap_or_default();
+        let query = query.trim();
+        rendered.visible = rendered.tree.filter(query);
+        rendered.unopened.clear();
+
+        let built = match &rendered.built {
+            Some(built) => built,
+            None => return,
+        };
+        for (element, &visible) in built.elements.iter().zip(&rendered.visible) {
+            if let Some(element) = element {
+                element
+                    .toggle_attribute_with_force("hidden", !visible)
+                    .unwrap_throw();
+            }
+        }
+        if query.is_empty() {
+            return;
+        }
+
+        rendered.unopened = (0..rendered.tree.nodes.len())
+            .filter(|&index| rendered.tree.nodes[index].rule.contains(query))
+            .collect();
+        self.open_matches(rendered);
+    }
+
+    /// Opens the ancestors of the next `OPENED_MATCHES` filter matches once
+    /// the tree is scrolled within a screenful of its end.
+    pub fn scrolled(&self, rendered: &mut RenderedTree) {
+        let container = &self.container;
+        let end = container.scroll_height() - container.client_height();
+        if container.scroll_top() + container.client_height() >= end {
+            self.open_matches(rendered);
+        }
+    }
+
+    fn open_matches(&self, rendered: &mut RenderedTree) {
+        if rendered.built.is_none() {
+            return;
+        }
+
+        for _ in 0..OPENED_MATCHES {
+            let index = match rendered.unopened.pop_front() {
+                Some(index) => index,
+                None => return,
+            };
+            let path = rendered.tree.path(index);
+            for &ancestor in &path[..path.len() - 1] {
+                self.expand(rendered, ancestor, true);
+            }
+        }
+    }
+
+    /// Returns the node the event happened in, if any.
+    pub fn node_at(&self, event: &Event) -> Option<usize> {
+        let target: Element = event.target()?.dyn_into().ok()?;
+
+        target
+            .closest("[data-node]")
+            .unwrap_throw()?
+            .get_attribute("data-node")?
+            .parse()
+            .ok()
+    }
+
+    /// Returns the rule name the event happened on, in a node label or the
+    /// breadcrumb.
+    pub fn rule_at(&self, event: &Event) -> Option<String> {
+        let target: Element = event.target()?.dyn_into().ok()?;
+
+        target.closest(".tree-rule").unwrap_throw()?.text_content()
+    }
+
+    /// Marks node `index` as selected and shows its path in the breadcrumb.
+    /// With `reveal`, also expands its ancestors and scrolls it into view.
+    ///
+    /// The node's children are created if they weren't yet, so a click that
+    /// opens it has something to show.
+    pub fn select(&self, rendered: &mut RenderedTree, index: usize, reveal: bool) {
+        let previous = rendered.selected.replace(index);
+        let path = rendered.tree.path(index);
+        let rules: Vec<_> = path
+            .iter()
+            .map(|&index| rendered.tree.nodes[index].rule.as_str())
+            .collect();
+        self.set_breadcrumb(&rules);
+
+        if rendered.built.is_none() {
+            return;
+        }
+        if reveal {
+            for &ancestor in &path[..path.len() - 1] {
+                self.expand(rendered, ancestor, true);
+            }
+        }
+        self.expand(rendered, index, false);
+
+        let elements = &rendered.built.as_ref().unwrap_throw().elements;
+        if let Some(previous) = previous.and_then(|previous| elements[previous].as_ref()) {
+            previous
+                .class_list()
+                .remove_1("tree-node-selected")
+                .unwrap_throw();
+        }
+        let element = elements[index].as_ref().unwrap_throw();
+        element
+            .class_list()
+            .add_1("tree-node-selected")
+            .unwrap_throw();
+        if reveal {
+            element.scroll_into_view_with_bool(false);
+        }
+    }
+
+    fn set_breadcrumb(&self, rules: &[&str]) {
+        let breadcrumb = match &self.breadcrumb {
+            Some(breadcrumb) => breadcrumb,
+            None => return,
+        };
+        let document = breadcrumb.owner_document().expect_throw("no document");
+
+        breadcrumb.set_inner_html("");
+        for (i, rule) in rules.iter().enumerate() {
+            if i > 0 {
+                breadcrumb
+                    .append_child(&document.create_text_node(" > "))
+                    .unwrap_throw();
+            }
+            append_span(&document, breadcrumb, "tree-rule", rule);
+        }
+    }
+
+    /// Creates the elements of the children of node `index` unless they
+    /// exist already. With `open`, also opens the node.
+    fn expand(&self, rendered: &mut RenderedTree, index: usize, open: bool) {
+        let built = rendered.built.as_mut().unwrap_throw();
+        if open {
+            if let Some(element) = &built.elements[index] {
+                element.set_attribute("open", "").unwrap_throw();
+            }
+        }
+        if std::mem::replace(&mut built.expanded[index], true) {
+            return;
+        }
+
+        for i in 0..rendered.tree.nodes[index].children.len() {
+            self.build_node(rendered, rendered.tree.nodes[index].children[i]);
+        }
+    }
+
+    /// Creates the element of node `index`, closed, inside its parent's,
+    /// which must exist.
+    fn build_node(&self, rendered: &mut RenderedTree, index: usize) {
+        let document = self.container.owner_document().expect_throw("no document");
+        let node = &rendered.tree.nodes[index];
+
+        let label = create(&document, "span", "tree-label");
+        append_span(&document, &label, "tree-rule", &node.rule);
+        if let Some(tag) = &node.tag {
+            append_span(&document, &label, "tree-tag", &format!("#{}", tag));
+        }
+        append_span(
+            &document,
+            &label,
+            "tree-range",
+            &format!("{}..{}", node.start, node.end),
+        );
+        append_span(
+            &document,
+            &label,
+            "tree-text",
+            &label_text(&rendered.input[node.start..node.end]),
+        );
+
+        let (element, children) = if node.children.is_empty() {
+            let leaf = create(&document, "div", "tree-node tree-leaf");
+            leaf.append_child(&label).unwrap_throw();
+            (leaf, None)
+        } else {
+            let details = create(&document, "details", "tree-node");
+            let summary = document.create_element("summary").unwrap_throw();
+            summary.append_child(&label).unwrap_throw();
+            let children = create(&document, "div", "tree-children");
+            details.append_child(&summary).unwrap_throw();
+            details.append_child(&children).unwrap_throw();
+            (details, Some(children))
+        };
+        element
+            .set_attribute("data-node", &index.to_string())
+            .unwrap_throw();
+        if rendered.visible.get(index) == Some(&false) {
+            element.set_attribute("hidden", "").unwrap_throw();
+        }
+
+        let built = rendered.built.as_mut().unwrap_throw();
+        let parent = match node.parent {
+            Some(parent) => built.child_lists[parent].as_ref().unwrap_throw(),
+            None => &self.container,
+        };
+        parent.append_child(&element).unwrap_throw();
+
+        built.elements[index] = Some(element);
+        built.child_lists[index] = children;
+    }
+}
+
+fn create(document: &Document, name: &str, class: &str) -> Element {
+    let element = document.create_element(name).unwrap_throw();
+    element.set_class_name(class);
+    element
+}
+
+fn append_span(document: &Document, parent: &Element, class: &str, text: &str) {
+    let span = create(document, "span", class);
+    span.set_text_content(Some(text));
+    parent.append_child(&span).unwrap_throw();
+}
+
+/// Quotes `text` like the text printer, cut to `LABEL_TEXT_CHARS` chars.
+fn label_text(text: &str) -> String {
+    match text.char_indices().nth(LABEL_TEXT_CHARS) {
+        Some((end, _)) => format!("{:?}…", &text[..end]),
+        None => format!("{:?}", text),
+    }
+}
@@ -0,0 +1,373 @@
+//! An interpreter for optimized grammars, following pest_vm's, that tells a
+//! `RuleTracker` about every rule it enters and leaves.
+//!
+//! pest_vm only reports rule entries, and a listener that stops a parse
+//! hands pest a fresh state, which breaks enclosing repetitions. Here a rule
+//! refused by the tracker simply fails, so the parse winds down on the state
+//! it had.
+//!
+//! This is a fork of pest_vm 2.9.3's `Vm`, which the tests compare it with.
+//! When bumping pest, carry over upstream changes to the rule and expression
+//! matching, and move the `pest_vm` dev-dependency to the same version.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use pest::error::{Error, ErrorVariant};
+use pest::iterators::Pairs;
+use pest::unicode;
+use pest::{Atomicity, MatchDir, ParseResult, ParserState, Position};
+use pest_meta::ast::RuleType;
+use pest_meta::optimizer::{OptimizedExpr, OptimizedRule};
+
+use crate::limits::{LimitError, ParseLimits, RuleTracker};
+
+type State<'a> = Box<ParserState<'a, &'a str>>;
+
+pub struct Vm {
+    rules: HashMap<String, OptimizedRule>,
+    tracker: RuleTracker,
+    /// A builtin rule the grammar called that this fork doesn't know, if
+    /// any. It fails like a rule that doesn't match.
+    unknown_builtin: RefCell<Option<String>>,
+}
+
+/// Why a parse returned no pairs.
+#[derive(Debug)]
+pub enum ParseFailure<'a> {
+    Error(Error<&'a str>),
+    /// The parse hit one of its limits. Whatever pest made of the rest of
+    /// the input is dropped.
+    Limit(LimitError),
+}
+
+impl Vm {
+    pub fn new(rules: Vec<OptimizedRule>) -> Vm {
+        Vm {
+            rules: rules
+                .into_iter()
+                .map(|rule| (rule.name.clone(), rule))
+                .collect(),
+            tracker: RuleTracker::default(),
+            unknown_builtin: RefCell::new(None),
+        }
+    }
+
+    /// Parses `input` starting at `rule` within `limits`.
+    pub fn parse<'a>(
+        &'a self,
+        rule: &'a str,
+        input: &'a str,
+        limits: &ParseLimits,
+    ) -> Result<Pairs<'a, &'a str>, ParseFailure<'a>> {
+        self.tracker.start(limits);
+        let result = pest::state(input, |state| self.parse_rule(rule, state));
+
+        if let Some(error) = self.tracker.finish() {
+            return Err(ParseFailure::Limit(error));
+        }
+        if let Some(builtin) = self.unknown_builtin.take() {
+            let error = Error::new_from_pos(
+                ErrorVariant::CustomError {
+                    message: format!("the builtin rule {} is not supported here", builtin),
+                },
+                Position::from_start(input),
+            );
+
+            return Err(ParseFailure::Error(error));
+        }
+
+        result.map_err(ParseFailure::Error)
+    }
+
+    fn parse_rule<'a>(&'a self, rule: &'a str, state: State<'a>) -> ParseResult<State<'a>> {
+        let state = state.check_stack_limit()?;
+        let defined = self.rules.get(rule);
+        if !self.tracker.enter(rule, defined.is_some()) {
+            return Err(state);
+        }
+
+        let result = match defined {
+            Some(defined) => self.parse_defined(defined, state),
+            None => self.parse_builtin(rule, state),
+        };
+        if defined.is_some() {
+            self.tracker.exit();
+        }
+
+        result
+    }
+
+    // pest's `match_range` includes the end of the range.
+    #[allow(clippy::almost_complete_range)]
+    fn parse_builtin<'a>(&'a self, rule: &'a str, state: State<'a>) -> ParseResult<State<'a>> {
+        match rule {
+            "ANY" => state.skip(1),
+            "EOI" => state.rule("EOI", |state| state.end_of_input()),
+            "SOI" => state.start_of_input(),
+            "PEEK" => state.stack_peek(),
+            "PEEK_ALL" => state.stack_match_peek(),
+            "POP" => state.stack_pop(),
+            "POP_ALL" => state.stack_match_pop(),
+            "DROP" => state.stack_drop(),
+            "ASCII_DIGIT" => state.match_range('0'..'9'),
+            "ASCII_NONZERO_DIGIT" => state.match_range('1'..'9'),
+            "ASCII_BIN_DIGIT" => state.match_range('0'..'1'),
+            "ASCII_OCT_DIGIT" => state.match_range('0'..'7'),
+            "ASCII_HEX_DIGIT" => state
+                .match_range('0'..'9')
+                .or_else(|state| state.match_range('a'..'f'))
+                .or_else(|state| state.match_range('A'..'F')),
+            "ASCII_ALPHA_LOWER" => state.match_range('a'..'z'),
+            "ASCII_ALPHA_UPPER" => state.match_range('A'..'Z'),
+            "ASCII_ALPHA" => state
+                .match_range('a'..'z')
+                .or_else(|state| state.match_range('A'..'Z')),
+            "ASCII_ALPHANUMERIC" => state
+                .match_range('a'..'z')
+                .or_else(|state| state.match_range('A'..'Z'))
+                .or_else(|state| state.match_range('0'..'9')),
+            "ASCII" => state.match_range('\x00'..'\x7f'),
+            "NEWLINE" => state
+                .match_string("\n")
+                .or_else(|state| state.match_string("\r\n"))
+                .or_else(|state| state.match_string("\r")),
+            _ => match unicode::by_name(rule) {
+                Some(property) => state.match_char_by(property),
+                // Validated grammars only call rules they define, so this is
+                // a builtin added to pest_meta after this fork.
+                None => {
+                    self.unknown_builtin.replace(Some(rule.to_owned()));
+                    Err(state)
+                }
+            },
+        }
+    }
+
+    fn parse_defined<'a>(
+        &'a self,
+        rule: &'a OptimizedRule,
+        state: State<'a>,
+    ) -> ParseResult<State<'a>> {
+        let name = rule.name.as_str();
+        let expr = |state| self.parse_expr(&rule.expr, state);
+
+        // Whitespace and comments are atomic inside, whatever their type.
+        if name == "WHITESPACE" || name == "COMMENT" {
+            return match rule.ty {
+                RuleType::Normal | RuleType::Atomic => {
+                    state.rule(name, |state| state.atomic(Atomicity::Atomic, expr))
+                }
+                RuleType::Silent => state.atomic(Atomicity::Atomic, expr),
+                RuleType::CompoundAtomic => {
+                    state.atomic(Atomicity::CompoundAtomic, |state| state.rule(name, expr))
+                }
+                RuleType::NonAtomic => {
+                    state.atomic(Atomicity::Atomic, |state| state.rule(name, expr))
+                }
+            };
+        }
+
+        match rule.ty {
+            RuleType::Normal => state.rule(name, expr),
+            RuleType::Silent => expr(state),
+            RuleType::Atomic => state.rule(name, |state| state.atomic(Atomicity::Atomic, expr)),
+            RuleType::CompoundAtomic => {
+                state.atomic(Atomicity::CompoundAtomic, |state| state.rule(name, expr))
+            }
+            RuleType::NonAtomic => {
+                state.atomic(Atomicity::NonAtomic, |state| state.rule(name, expr))
+            }
+        }
+    }
+
+    fn parse_expr<'a>(
+        &'a self,
+        expr: &'a OptimizedExpr,
+        state: State<'a>,
+    ) -> ParseResult<State<'a>> {
+        let state = state.check_stack_limit()?;
+
+        match expr {
+            OptimizedExpr::Str(string) => state.match_string(string),
+            OptimizedExpr::Insens(string) => state.match_insensitive(string),
+            OptimizedExpr::Range(start, end) => {
+                let start = start.chars().next().expect("empty char literal");
+                let end = end.chars().next().expect("empty char literal");
+
+                state.match_range(start..end)
+            }
+            OptimizedExpr::Ident(name) => self.parse_rule(name, state),
+            OptimizedExpr::PeekSlice(start, end) => {
+                state.stack_match_peek_slice(*start, *end, MatchDir::BottomToTop)
+            }
+            OptimizedExpr::PosPred(expr) => {
+                state.lookahead(true, |state| self.parse_expr(expr, state))
+            }
+            OptimizedExpr::NegPred(expr) => {
+                state.lookahead(false, |state| self.parse_expr(expr, state))
+            }
+            OptimizedExpr::Seq(lhs, rhs) => state.sequence(|state| {
+                self.parse_expr(lhs, state)
+                    .and_then(|state| self.skip(state))
+                    .and_then(|state| self.parse_expr(rhs, state))
+            }),
+            OptimizedExpr::Choice(lhs, rhs) => self
+                .parse_expr(lhs, state)
+                .or_else(|state| self.parse_expr(rhs, state)),
+            OptimizedExpr::Opt(expr) => state.optional(|state| self.parse_expr(expr, state)),
+            OptimizedExpr::Rep(expr) => state.sequence(|state| {
+                state.optional(|state| {
+                    self.parse_expr(expr, state)
+                        .and_then(|state| self.repeat(expr, state))
+                })
+            }),
+            OptimizedExpr::RepOnce(expr) => state.sequence(|state| {
+                self.parse_expr(expr, state)
+                    .and_then(|state| self.repeat(expr, state))
+            }),
+            OptimizedExpr::Push(expr) => state.stack_push(|state| self.parse_expr(expr, state)),
+            OptimizedExpr::PushLiteral(string) => state.stack_push_literal(string.to_owned()),
+            OptimizedExpr::Skip(strings) => {
+                let strings: Vec<_> = strings.iter().map(String::as_str).collect();
+                state.skip_until(&strings)
+            }
+            OptimizedExpr::NodeTag(expr, tag) => self
+                .parse_expr(expr, state)
+                .and_then(|state| state.tag_node(tag)),
+            OptimizedExpr::RestoreOnErr(expr) => {
+                state.restore_on_err(|state| self.parse_expr(expr, state))
+            }
+        }
+    }
+
+    /// Matches `expr` as often as it goes, with whitespace and comments
+    /// before every match.
+    fn repeat<'a>(&'a self, expr: &'a OptimizedExpr, state: State<'a>) -> ParseResult<State<'a>> {
+        state.repeat(|state| {
+            state.sequence(|state| {
+                self.skip(state)
+                    .and_then(|state| self.parse_expr(expr, state))
+            })
+        })
+    }
+
+    /// Skips the implicit whitespace and comments between tokens of
+    /// non-atomic rules.
+    fn skip<'a>(&'a self, state: State<'a>) -> ParseResult<State<'a>> {
+        if state.atomicity() != Atomicity::NonAtomic {
+            return Ok(state);
+        }
+
+        let whitespace = |state| self.parse_rule("WHITESPACE", state);
+        let comment = |state| self.parse_rule("COMMENT", state);
+
+        match (
+            self.rules.contains_key("WHITESPACE"),
+            self.rules.contains_key("COMMENT"),
+        ) {
+            (false, false) => Ok(state),
+            (true, false) => state.repeat(whitespace),
+            (false, true) => state.repeat(comment),
+            (true, true) => state.sequence(|state| {
+                state.repeat(whitespace).and_then(|state| {
+                    state.repeat(|state| {
+                        state.sequence(|state| {
+                            comment(state).and_then(|state| state.repeat(whitespace))
+                        })
+                    })
+                })
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pest_meta::optimizer::OptimizedExpr;
+
+    use super::*;
+
+    /// Parses every input with this VM and with pest_vm, and checks that
+    /// they agree on the pairs or the error.
+    fn assert_matches_pest_vm(grammar: &str, rule: &str, inputs: &[&str]) {
+        let (_, rules) = pest_meta::parse_and_optimize(grammar).unwrap();
+        let ours = Vm::new(rules.clone());
+        let upstream = pest_vm::Vm::new(rules);
+
+        for input in inputs {
+            let expected = match upstream.parse(rule, input) {
+                Ok(pairs) => format!("{:?}", pairs),
+                Err(error) => error.to_string(),
+            };
+            let actual = match ours.parse(rule, input, &ParseLimits::unlimited()) {
+                Ok(pairs) => format!("{:?}", pairs),
+                Err(ParseFailure::Error(error)) => error.to_string(),
+                Err(ParseFailure::Limit(error)) => panic!("unlimited parse hit {:?}", error),
+            };
+
+            assert_eq!(actual, expected, "{:?} on {:?}", rule, input);
+        }
+    }
+
+    #[test]
+    fn matches_pest_vm_on_implicit_whitespace_and_comments() {
+        assert_matches_pest_vm(
+            "WHITESPACE = _{ \" \" | \"\\n\" }\nCOMMENT = { \"#\" ~ (!\"\\n\" ~ ANY)* }\n\
+             list = { SOI ~ \"[\" ~ (item ~ (\",\" ~ item)*)? ~ \"]\" ~ EOI }\n\
+             item = @{ ASCII_DIGIT+ }",
+            "list",
+            &["[]", "[ 1 , 23 ]", "[1 # one\n, 2]", "[1 2]", "[ 1,", ""],
+        );
+    }
+
+    #[test]
+    fn matches_pest_vm_on_atomicity_and_silent_rules() {
+        assert_matches_pest_vm(
+            "WHITESPACE = _{ \" \" }\n\
+             doc = { SOI ~ (word | quoted | pair)* ~ EOI }\n\
+             word = @{ ASCII_ALPHA+ }\n\
+             quoted = ${ \"'\" ~ inner ~ \"'\" }\n\
+             inner = @{ (!\"'\" ~ ANY)* }\n\
+             pair = !{ key ~ \"=\" ~ word }\n\
+             key = _{ ^\"k\" ~ '0'..'9' }",
+            "doc",
+            &["ab cd", "'a b' x", "K1 = v", "k1=v", "'open", "a ="],
+        );
+    }
+
+    #[test]
+    fn matches_pest_vm_on_the_stack_tags_and_builtins() {
+        assert_matches_pest_vm(
+            "raw = { SOI ~ PUSH(\"#\"*) ~ \"\\\"\" ~ body ~ \"\\\"\" ~ POP ~ tail ~ EOI }\n\
+             body = { (!(\"\\\"\" ~ PEEK) ~ ANY)* }\n\
+             tail = { #kind = (NEWLINE | LETTER | ASCII_HEX_DIGIT)? ~ &EOI }",
+            "raw",
+            &["\"a\"", "##\"a\"#b\"##", "#\"x\"#\\n", "##\"x\"#", "\"\"é"],
+        );
+        assert_matches_pest_vm(
+            "nested = { SOI ~ open ~ close ~ EOI }\n\
+             open = _{ PUSH(\"(\") ~ open? }\n\
+             close = { PEEK[..] ~ DROP* }",
+            "nested",
+            &["((((", "(()", ""],
+        );
+    }
+
+    #[test]
+    fn unknown_builtins_fail_the_parse_instead_of_panicking() {
+        let rules = vec![OptimizedRule {
+            name: String::from("main"),
+            ty: RuleType::Normal,
+            expr: OptimizedExpr::Ident(String::from("SOME_FUTURE_BUILTIN")),
+        }];
+        let vm = Vm::new(rules);
+
+        match vm.parse("main", "x", &ParseLimits::unlimited()) {
+            Err(ParseFailure::Error(error)) => {
+                assert!(error.to_string().contains("SOME_FUTURE_BUILTIN"))
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
@@ -3,10 +3,37 @@
 declare const CodeMirror: any;
 
 import Split from "split.js";
-import init, { lint, format } from "../../pkg/pest_site.js";
+import init, {
+  Playground,
+  format,
+  format_range,
+  take_panic,
+} from "../../pkg/pest_site.js";
 import { initShareButton } from "./shareButton";
+import { getFormatOptions, initFormatOptions } from "./formatOptions";
+import { ParserClient } from "./parserClient";
+import {
+  ExportOptions,
+  getExportOptions,
+  getJsonOptions,
+  getPrinterOptions,
+  initPrinterOptions,
+  JsonOptions,
+  PrinterOptions,
+  setExportOptions,
+  setJsonOptions,
+  setPrinterOptions,
+} from "./printerOptions";
+import {
+  getDebounce,
+  getParseLimits,
+  initParseLimits,
+  ParseLimits,
+} from "./parseLimits";
 
 let loaded = false;
+// eslint-disable-next-line @typescript-eslint/no-explicit-any
+const playgrounds = new WeakMap<any, Playground>();
 
 const editorDom = document.querySelector<HTMLLinkElement>(".editor")!;
 const gridDom = document.querySelector<HTMLDivElement>(".editor-grid")!;
@@ -15,8 +42,29 @@ const inputTextDom =
   document.querySelector<HTMLTextAreaElement>(".editor-input-text")!;
 const outputDom =
   document.querySelector<HTMLTextAreaElement>(".editor-output")!;
+const outputPanicDom = document.querySelector<HTMLParagraphElement>(
+  ".editor-output-panic",
+)!;
+const outputCopyBtn =
+  document.querySelector<HTMLButtonElement>("#outputCopyBtn")!;
+const outputDownloadBtn =
+  document.querySelector<HTMLButtonElement>("#outputDownloadBtn")!;
 const modeBtn = document.querySelector<HTMLButtonElement>("#modeBtn")!;
 const formatBtn = document.querySelector<HTMLButtonElement>("#formatBtn")!;
+const formatRuleBtn =
+  document.querySelector<HTMLButtonElement>("#formatRuleBtn")!;
+const formatWarning =
+  document.querySelector<HTMLParagraphElement>("#formatWarning")!;
+const formatPreview = document.querySelector<HTMLDivElement>(".format-preview")!;
+const formatPreviewStatus = document.querySelector<HTMLParagraphElement>(
+  ".format-preview-status",
+)!;
+const formatPreviewDiff =
+  document.querySelector<HTMLPreElement>(".format-preview-diff")!;
+const formatApplyBtn =
+  document.querySelector<HTMLButtonElement>("#formatApplyBtn")!;
+const formatCancelBtn =
+  document.querySelector<HTMLButtonElement>("#formatCancelBtn")!;
 
 const windowHeight = window.innerHeight;
 
@@ -54,15 +102,45 @@ CodeMirror.defineSimpleMode("pest", {
   },
 });
 
-CodeMirror.registerHelper("lint", "pest", function (text) {
-  if (loaded) {
+type Position = { line: number; ch: number };
+type Diagnostic = {
+  from: Position;
+  to: Position;
+  severity: "error" | "warning";
+  code: string;
+  message: string;
+};
+
+type RuleDefinition = { name: string; from: Position; to: Position };
+
+type DiffLine = { kind: "equal" | "delete" | "insert"; text: string };
+type FormatResult =
+  | {
+      status: "ok";
+      formatted: string;
+      diff: DiffLine[];
+      idempotent: boolean;
+      equivalent: boolean | null;
+    }
+  | { status: "error"; errors: Diagnostic[] };
+
+const diffMarkers = { equal: " ", delete: "-", insert: "+" };
+
+CodeMirror.registerHelper("lint", "pest", function (text, _options, cm) {
+  const playground = playgrounds.get(cm);
+  if (playground) {
     const doc = CodeMirror.Doc(text);
-    const errors = lint(text);
-    const mapped: { message: string; from: number; to: number }[] = [];
+    const errors: Diagnostic[] = playground.lint(text);
+    const mapped: {
+      message: string;
+      severity: string;
+      from: Position;
+      to: Position;
+    }[] = [];
 
-    for (let i = 0; i < errors.length; i++) {
-      let from = doc.clipPos(eval("CodeMirror.Pos" + errors[i].get("from")));
-      let to = doc.clipPos(eval("CodeMirror.Pos" + errors[i].get("to")));
+    for (const error of errors) {
+      let from = doc.clipPos(CodeMirror.Pos(error.from.line, error.from.ch));
+      let to = doc.clipPos(CodeMirror.Pos(error.to.line, error.to.ch));
 
       if (from.line === to.line && from.ch === to.ch) {
         to.ch += 1;
@@ -75,7 +153,8 @@ CodeMirror.registerHelper("lint", "pest", function (text) {
       }
 
       mapped.push({
-        message: errors[i].get("message"),
+        message: error.message,
+        severity: error.severity,
         from: from,
         to: to,
       });
@@ -95,6 +174,20 @@ const myCodeMirror = CodeMirror.fromTextArea(grammar, {
   placeholder: "Grammar",
 });
 
+initFormatOptions();
+initParseLimits(() => {
+  const playground = playgrounds.get(myCodeMirror);
+  if (playground) {
+    applyParseLimits(playground);
+  }
+});
+initPrinterOptions(() => {
+  const playground = playgrounds.get(myCodeMirror);
+  if (playground) {
+    applyOutputOptions(playground);
+  }
+  saveCode();
+});
 initShareButton({ myCodeMirror });
 
 function doFormat() {
@@ -103,8 +196,112 @@ function doFormat() {
   }
 
   const grammar = myCodeMirror.getValue();
-  const formatted = format(grammar);
-  myCodeMirror.setValue(formatted);
+  const result: FormatResult = format(grammar, getFormatOptions());
+
+  if (result.status === "error") {
+    const [error] = result.errors;
+    if (error.code === "options") {
+      showFormatWarning(`Check the format options: ${error.message}`);
+      return;
+    }
+    showFormatWarning(`Fix syntax errors before formatting: ${error.message}`);
+    myCodeMirror.setSelection(error.from, error.to);
+    myCodeMirror.focus();
+  } else if (result.diff.every((line) => line.kind === "equal")) {
+    showFormatWarning("Already formatted.");
+  } else {
+    showFormatPreview(result);
+  }
+}
+
+// Reformats only the rules touched by the selection, or the rule under the
+// cursor.
+function doFormatRule() {
+  if (!loaded || !myCodeMirror) {
+    return;
+  }
+
+  const grammar = myCodeMirror.getValue();
+  const encoder = new TextEncoder();
+  const byteOffset = (pos: Position) =>
+    encoder.encode(grammar.slice(0, myCodeMirror.indexFromPos(pos))).length;
+  const target = {
+    start: byteOffset(myCodeMirror.getCursor("from")),
+    end: byteOffset(myCodeMirror.getCursor("to")),
+  };
+  const result = format_range(grammar, target, getFormatOptions());
+
+  if (result.status === "error") {
+    const [error] = result.errors;
+    showFormatWarning(
+      error.code === "options"
+        ? `Check the format options: ${error.message}`
+        : `Fix syntax errors before formatting: ${error.message}`,
+    );
+  } else if (result.edit) {
+    clearFormatWarning();
+    const { from, to, text } = result.edit;
+    myCodeMirror.replaceRange(text, from, to);
+  }
+}
+
+function showFormatPreview(result: FormatResult & { status: "ok" }) {
+  clearFormatWarning();
+  formatPreviewDiff.replaceChildren(
+    ...result.diff.map((line) => {
+      const span = document.createElement("span");
+      span.className = `diff-${line.kind}`;
+      span.innerText = `${diffMarkers[line.kind]} ${line.text}\n`;
+      return span;
+    }),
+  );
+
+  if (result.equivalent === false) {
+    formatPreviewStatus.innerText =
+      "The formatted grammar compiles to different rules; it was not applied.";
+  } else if (!result.idempotent) {
+    formatPreviewStatus.innerText =
+      "Formatting again would change the result further.";
+  } else {
+    formatPreviewStatus.innerText = "";
+  }
+  formatApplyBtn.disabled = result.equivalent === false;
+  formatApplyBtn.onclick = () => {
+    const cursor = myCodeMirror.getCursor();
+    myCodeMirror.setValue(result.formatted);
+    myCodeMirror.setCursor(mapLine(result.diff, cursor.line), cursor.ch);
+    hideFormatPreview();
+  };
+  formatPreview.style.display = "";
+}
+
+function hideFormatPreview() {
+  formatPreview.style.display = "none";
+}
+
+// Maps a line of the original grammar to the formatted one by walking the
+// diff; deleted lines map to the next surviving line.
+function mapLine(diff: DiffLine[], line: number) {
+  let oldLine = 0;
+  let newLine = 0;
+  for (const { kind } of diff) {
+    if (kind !== "insert" && oldLine === line) {
+      return newLine;
+    }
+    if (kind !== "insert") oldLine++;
+    if (kind !== "delete") newLine++;
+  }
+  return newLine;
+}
+
+function showFormatWarning(message: string) {
+  formatWarning.innerText = message;
+  formatWarning.style.display = "";
+}
+
+function clearFormatWarning() {
+  formatWarning.innerText = "";
+  formatWarning.style.display = "none";
 }
 
 let split: Split.Instance | null = null;
@@ -133,14 +330,24 @@ function makeResizable(wideMode: boolean) {
 type SavedGrammar = {
   grammar: string;
   input: string;
+  printer?: PrinterOptions;
+  json?: JsonOptions;
+  export?: ExportOptions;
 };
 function saveCode() {
   const grammar = myCodeMirror.getValue();
   const input = inputTextDom.value;
-  const json = JSON.stringify({ grammar, input } satisfies SavedGrammar);
+  const printer = getPrinterOptions();
+  const json = JSON.stringify({
+    grammar,
+    input,
+    printer,
+    json: getJsonOptions(),
+    export: getExportOptions(),
+  } satisfies SavedGrammar);
   localStorage.setItem("last-editor-state", json);
 }
-function getSavedCode() {
+function getSavedCode(): SavedGrammar {
   const json = localStorage.getItem("last-editor-state");
   const parsed = JSON.parse(json || "null");
   return parsed || { grammar: "", input: "" };
@@ -170,19 +377,141 @@ function restore() {
   makeResizable(false);
 }
 
+type ExportedOutput = { text: string; fileName: string; mimeType: string };
+
+// Copy and download export the last successful parse in the selected output
+// mode; the tree view exports JSON.
+outputCopyBtn.onclick = () => {
+  const output: ExportedOutput | undefined = playgrounds
+    .get(myCodeMirror)
+    ?.export();
+  if (output) {
+    navigator.clipboard.writeText(output.text);
+  }
+};
+outputDownloadBtn.onclick = () => {
+  const output: ExportedOutput | undefined = playgrounds
+    .get(myCodeMirror)
+    ?.export();
+  if (!output) {
+    return;
+  }
+
+  const link = document.createElement("a");
+  const blob = new Blob([output.text], { type: output.mimeType });
+  link.href = URL.createObjectURL(blob);
+  link.download = output.fileName;
+  link.click();
+  URL.revokeObjectURL(link.href);
+};
+
 modeBtn.onclick = wideMode;
 formatBtn.onclick = doFormat;
+formatRuleBtn.onclick = doFormatRule;
+formatCancelBtn.onclick = hideFormatPreview;
+
+const parserClient = new ParserClient();
+
+// Aborted to drop the playground after a panic, when it can't be called.
+let playgroundController: AbortController;
+
+// The playground keeps its previous settings when it rejects new ones and
+// says why.
+function applyParseLimits(playground: Playground) {
+  reportOptionsErrors([
+    playground.set_debounce(getDebounce()),
+    playground.set_limits(getParseLimits()),
+  ]);
+}
+function applyOutputOptions(playground: Playground) {
+  reportOptionsErrors([
+    playground.set_printer(getPrinterOptions()),
+    playground.set_json_options(getJsonOptions()),
+    playground.set_export_options(getExportOptions()),
+  ]);
+}
+function reportOptionsErrors(errors: (string | undefined)[]) {
+  const error = errors.find((error) => error !== undefined);
+  if (error !== undefined) {
+    showFormatWarning(`Check the options: ${error}`);
+  }
+}
+
+function attachPlayground() {
+  playgroundController = new AbortController();
+  const playground = new Playground(editorDom, playgroundController.signal);
+  playground.set_definition_handler((definition: RuleDefinition) => {
+    myCodeMirror.focus();
+    myCodeMirror.setSelection(definition.from, definition.to, { scroll: true });
+  });
+  applyParseLimits(playground);
+  applyOutputOptions(playground);
+  playground.set_parser(
+    (grammar: string, rule: string, input: string, limits: ParseLimits) =>
+      parserClient.parse(grammar, rule, input, limits),
+  );
+  playgrounds.set(myCodeMirror, playground);
+}
+
+// A panic traps the wasm call that hit it and leaves the module's memory
+// half-updated. Taking the panic makes the next call run on a fresh instance,
+// so detach the old playground without calling into it, drop the parses it
+// is waiting for and attach a new one. The next edit compiles the grammar
+// again.
+function recoverFromPanic() {
+  const panic = loaded && take_panic();
+  if (!panic) {
+    return;
+  }
+
+  playgroundController.abort();
+  parserClient.abandon();
+  playgrounds.delete(myCodeMirror);
+  attachPlayground();
+
+  const location = panic.location ? ` at ${panic.location}` : "";
+  outputDom.value = `The playground panicked${location}:\n${panic.message}`;
+
+  // The grammar and input can be long or private, so the reporter is asked
+  // to attach them rather than having them put into the link.
+  const issue = new URL("https://github.com/pest-parser/site/issues/new");
+  issue.searchParams.set("title", `Playground panic: ${panic.message}`);
+  issue.searchParams.set(
+    "body",
+    `The playground panicked${location}:\n\n    ${panic.message}\n\n` +
+      "Grammar and input (or a share link from the playground):\n",
+  );
+  outputPanicDom.querySelector("a")!.href = issue.toString();
+  outputPanicDom.hidden = false;
+}
+
+window.addEventListener("error", recoverFromPanic);
+window.addEventListener("unhandledrejection", recoverFromPanic);
 
 init().then(() => {
   loaded = true;
+  attachPlayground();
   const url = new URL(window.location.href);
   const hasUrlGrammar = url.searchParams.get("g");
   if (!hasUrlGrammar) {
-    const { grammar, input } = getSavedCode();
+    const { grammar, input, printer, json, export: exportOptions } =
+      getSavedCode();
+    setPrinterOptions(printer ?? {});
+    setJsonOptions(json ?? {});
+    setExportOptions(exportOptions ?? {});
+    const playground = playgrounds.get(myCodeMirror);
+    if (playground) {
+      applyOutputOptions(playground);
+    }
     myCodeMirror.setValue(grammar);
     inputTextDom.value = input;
   }
+  myCodeMirror.performLint();
 });
 
 inputTextDom.addEventListener("input", saveCode);
 myCodeMirror.on("change", saveCode);
+myCodeMirror.on("change", clearFormatWarning);
+myCodeMirror.on("change", hideFormatPreview);
+myCodeMirror.on("change", () => (outputPanicDom.hidden = true));
+inputTextDom.addEventListener("input", () => (outputPanicDom.hidden = true));
@@ -0,0 +1,62 @@
+export type FormatOptions = {
+  indentWidth: number;
+  choiceWrapWidth: number | null;
+  alignAssignments: boolean;
+  blankLinesBetweenRules: number | null;
+  comments: "keep" | "strip";
+};
+
+const defaultFormatOptions: FormatOptions = {
+  indentWidth: 4,
+  choiceWrapWidth: null,
+  alignAssignments: true,
+  blankLinesBetweenRules: null,
+  comments: "keep",
+};
+
+let form: HTMLFormElement;
+
+export function initFormatOptions() {
+  form = document.querySelector<HTMLFormElement>("form.format-options")!;
+  setFormatOptions(loadFormatOptions());
+  form.addEventListener("change", () => {
+    localStorage.setItem("format-options", JSON.stringify(getFormatOptions()));
+  });
+}
+
+export function getFormatOptions(): FormatOptions {
+  const optionalNumber = (name: string) =>
+    field(name).value === "" ? null : Number(field(name).value);
+
+  return {
+    indentWidth: Number(field("indentWidth").value),
+    choiceWrapWidth: optionalNumber("choiceWrapWidth"),
+    alignAssignments: (field("alignAssignments") as HTMLInputElement).checked,
+    blankLinesBetweenRules: optionalNumber("blankLinesBetweenRules"),
+    comments: field("comments").value as FormatOptions["comments"],
+  };
+}
+
+export function setFormatOptions(options: Partial<FormatOptions>) {
+  const merged = { ...defaultFormatOptions, ...options };
+
+  field("indentWidth").value = String(merged.indentWidth);
+  field("choiceWrapWidth").value = String(merged.choiceWrapWidth ?? "");
+  (field("alignAssignments") as HTMLInputElement).checked =
+    merged.alignAssignments;
+  field("blankLinesBetweenRules").value = String(
+    merged.blankLinesBetweenRules ?? "",
+  );
+  field("comments").value = merged.comments;
+}
+
+function field(name: string) {
+  return form.elements.namedItem(name) as
+    | HTMLInputElement
+    | HTMLSelectElement;
+}
+
+function loadFormatOptions(): Partial<FormatOptions> {
+  const parsed = JSON.parse(localStorage.getItem("format-options") ?? "null");
+  return parsed && typeof parsed === "object" ? parsed : {};
+}
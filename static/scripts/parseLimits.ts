@@ -0,0 +1,70 @@
+export type ParseLimits = {
+  callLimit: number | null;
+  depthLimit: number | null;
+};
+
+export type Debounce = {
+  maxDelayMs: number;
+};
+
+const defaultParseLimits: ParseLimits = {
+  callLimit: 10_000_000,
+  depthLimit: 1_000,
+};
+
+const defaultDebounce: Debounce = {
+  maxDelayMs: 800,
+};
+
+let form: HTMLFormElement;
+
+export function initParseLimits(onChange: () => void) {
+  form = document.querySelector<HTMLFormElement>("form.parse-limits")!;
+  setParseLimits(load("parse-limits"));
+  setDebounce(load("parse-debounce"));
+  form.addEventListener("change", () => {
+    localStorage.setItem("parse-limits", JSON.stringify(getParseLimits()));
+    localStorage.setItem("parse-debounce", JSON.stringify(getDebounce()));
+    onChange();
+  });
+}
+
+export function getParseLimits(): ParseLimits {
+  const optionalNumber = (name: string) =>
+    field(name).value === "" ? null : Number(field(name).value);
+
+  return {
+    callLimit: optionalNumber("callLimit"),
+    depthLimit: optionalNumber("depthLimit"),
+  };
+}
+
+export function setParseLimits(limits: Partial<ParseLimits>) {
+  const merged = { ...defaultParseLimits, ...limits };
+
+  field("callLimit").value = String(merged.callLimit ?? "");
+  field("depthLimit").value = String(merged.depthLimit ?? "");
+}
+
+export function getDebounce(): Debounce {
+  const maxDelayMs = field("maxDelayMs").value;
+  return {
+    maxDelayMs:
+      maxDelayMs === "" ? defaultDebounce.maxDelayMs : Number(maxDelayMs),
+  };
+}
+
+function setDebounce(debounce: Partial<Debounce>) {
+  const merged = { ...defaultDebounce, ...debounce };
+
+  field("maxDelayMs").value = String(merged.maxDelayMs);
+}
+
+function field(name: string) {
+  return form.elements.namedItem(name) as HTMLInputElement;
+}
+
+function load(key: string) {
+  const parsed = JSON.parse(localStorage.getItem(key) ?? "null");
+  return parsed && typeof parsed === "object" ? parsed : {};
+}
@@ -0,0 +1,36 @@
+import init, { Session, take_panic } from "../../pkg/pest_site.js";
+import type { ParseLimits } from "./parseLimits";
+
+export type ParseRequest = {
+  id: number;
+  grammar?: string;
+  rule: string;
+  input: string;
+  limits: ParseLimits;
+};
+
+let initialized = false;
+const ready = init().then(() => {
+  initialized = true;
+  return new Session();
+});
+
+self.addEventListener("message", async (event: MessageEvent<ParseRequest>) => {
+  const { id, grammar, rule, input, limits } = event.data;
+
+  try {
+    const session = await ready;
+    if (grammar !== undefined) {
+      session.compile(grammar);
+    }
+    self.postMessage({ id, result: session.parse(rule, input, limits) });
+  } catch (error) {
+    // A panic leaves the session unusable, and a failed init never gives
+    // one; either way the client replaces this worker.
+    const panic = initialized ? take_panic() : null;
+    self.postMessage({
+      id,
+      error: panic ? `parser panicked: ${panic.message}` : String(error),
+    });
+  }
+});
@@ -0,0 +1,141 @@
+import type { ParseLimits } from "./parseLimits";
+import type { ParseRequest } from "./parseWorker";
+
+export type ParseOutcome =
+  | { status: "ok"; tree: unknown; compileMs: number | null; parseMs: number }
+  | { status: "error"; diagnostic: unknown }
+  | { status: "limit"; error: unknown }
+  | { status: "cancelled" };
+
+type Pending = {
+  request: ParseRequest;
+  resolve: (outcome: ParseOutcome) => void;
+  reject: (error: unknown) => void;
+};
+
+// How long a superseded parse may keep running before its worker is
+// replaced. Most parses finish well within it, so the worker and its
+// compiled grammar are usually kept.
+const SUPERSEDED_GRACE_MS = 250;
+
+// Runs parses in a Web Worker, one at a time. A parse requested while
+// another is running waits for it and replaces any request already
+// waiting; superseded requests resolve as cancelled. If the running parse
+// doesn't finish within SUPERSEDED_GRACE_MS of being superseded, the worker
+// is terminated and respawned, so a runaway grammar never blocks newer
+// edits, even without parse limits.
+export class ParserClient {
+  private worker!: Worker;
+  private compiled: string | null = null;
+  private running: Pending | null = null;
+  private waiting: Pending | null = null;
+  private graceTimer: number | undefined;
+  private nextId = 0;
+
+  constructor() {
+    this.spawn();
+  }
+
+  parse(
+    grammar: string,
+    rule: string,
+    input: string,
+    limits: ParseLimits,
+  ): Promise<ParseOutcome> {
+    this.running?.resolve({ status: "cancelled" });
+    this.waiting?.resolve({ status: "cancelled" });
+    this.waiting = null;
+
+    return new Promise((resolve, reject) => {
+      const request: ParseRequest = {
+        id: this.nextId++,
+        grammar,
+        rule,
+        input,
+        limits,
+      };
+      this.waiting = { request, resolve, reject };
+      if (!this.running) {
+        this.sendWaiting();
+      } else if (this.graceTimer === undefined) {
+        const superseded = this.running;
+        this.graceTimer = window.setTimeout(() => {
+          this.graceTimer = undefined;
+          if (this.running === superseded) {
+            this.restart();
+          }
+        }, SUPERSEDED_GRACE_MS);
+      }
+    });
+  }
+
+  // Forgets the running and waiting requests without settling them, for
+  // callers that can no longer handle the outcome.
+  abandon() {
+    this.running = null;
+    this.waiting = null;
+    this.clearGraceTimer();
+  }
+
+  private sendWaiting() {
+    const pending = this.waiting;
+    this.waiting = null;
+    this.running = pending;
+    if (!pending) {
+      return;
+    }
+
+    // The worker keeps the last grammar it compiled.
+    const { request } = pending;
+    if (request.grammar === this.compiled) {
+      delete request.grammar;
+    } else {
+      this.compiled = request.grammar!;
+    }
+    this.worker.postMessage(request);
+  }
+
+  // Replaces the worker and moves on to the waiting request. The running
+  // one was superseded or failed and has been settled already.
+  private restart() {
+    this.clearGraceTimer();
+    this.worker.terminate();
+    this.running = null;
+    this.spawn();
+    this.sendWaiting();
+  }
+
+  private clearGraceTimer() {
+    window.clearTimeout(this.graceTimer);
+    this.graceTimer = undefined;
+  }
+
+  private spawn() {
+    this.compiled = null;
+    this.worker = new Worker(new URL("./parseWorker.ts", import.meta.url), {
+      type: "module",
+    });
+    this.worker.addEventListener("message", (event) => {
+      const { id, result, error } = event.data;
+      const running = this.running;
+      if (running?.request.id !== id) {
+        return;
+      }
+      // A panic leaves the worker's wasm instance unusable.
+      if (error !== undefined) {
+        running.reject(error);
+        this.restart();
+        return;
+      }
+      this.clearGraceTimer();
+      running.resolve(result);
+      this.sendWaiting();
+    });
+    // Errors outside the message handler, like a worker script that fails
+    // to load, leave the worker in an unknown state.
+    this.worker.addEventListener("error", (event) => {
+      this.running?.reject(event.message);
+      this.restart();
+    });
+  }
+}
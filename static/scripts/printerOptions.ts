@@ -0,0 +1,104 @@
+export type PrinterOptions = {
+  indentWidth: number;
+  collapseChains: boolean;
+  spans: "none" | "bytes" | "lineCol";
+  maxTextChars: number | null;
+  innerText: boolean;
+  hiddenRules: string[];
+};
+
+export type JsonOptions = {
+  lineCol: boolean;
+  tags: boolean;
+};
+
+export type ExportOptions = {
+  spans: boolean;
+};
+
+const defaultPrinterOptions: PrinterOptions = {
+  indentWidth: 2,
+  collapseChains: true,
+  spans: "none",
+  maxTextChars: null,
+  innerText: false,
+  hiddenRules: [],
+};
+
+const defaultJsonOptions: JsonOptions = {
+  lineCol: false,
+  tags: false,
+};
+
+const defaultExportOptions: ExportOptions = {
+  spans: false,
+};
+
+let form: HTMLFormElement;
+
+export function initPrinterOptions(onChange: () => void) {
+  form = document.querySelector<HTMLFormElement>("form.printer-options")!;
+  setPrinterOptions({});
+  setJsonOptions({});
+  setExportOptions({});
+  form.addEventListener("change", onChange);
+}
+
+export function getPrinterOptions(): PrinterOptions {
+  const maxTextChars = field("maxTextChars").value;
+
+  return {
+    indentWidth: Number(field("indentWidth").value),
+    collapseChains: (field("collapseChains") as HTMLInputElement).checked,
+    spans: field("spans").value as PrinterOptions["spans"],
+    maxTextChars: maxTextChars === "" ? null : Number(maxTextChars),
+    innerText: (field("innerText") as HTMLInputElement).checked,
+    hiddenRules: field("hiddenRules")
+      .value.split(",")
+      .map((rule) => rule.trim())
+      .filter((rule) => rule !== ""),
+  };
+}
+
+export function setPrinterOptions(options: Partial<PrinterOptions>) {
+  const merged = { ...defaultPrinterOptions, ...options };
+
+  field("indentWidth").value = String(merged.indentWidth);
+  (field("collapseChains") as HTMLInputElement).checked = merged.collapseChains;
+  field("spans").value = merged.spans;
+  field("maxTextChars").value = String(merged.maxTextChars ?? "");
+  (field("innerText") as HTMLInputElement).checked = merged.innerText;
+  field("hiddenRules").value = merged.hiddenRules.join(", ");
+}
+
+export function getJsonOptions(): JsonOptions {
+  return {
+    lineCol: (field("jsonLineCol") as HTMLInputElement).checked,
+    tags: (field("jsonTags") as HTMLInputElement).checked,
+  };
+}
+
+export function setJsonOptions(options: Partial<JsonOptions>) {
+  const merged = { ...defaultJsonOptions, ...options };
+
+  (field("jsonLineCol") as HTMLInputElement).checked = merged.lineCol;
+  (field("jsonTags") as HTMLInputElement).checked = merged.tags;
+}
+
+export function getExportOptions(): ExportOptions {
+  return {
+    spans: (field("exportSpans") as HTMLInputElement).checked,
+  };
+}
+
+export function setExportOptions(options: Partial<ExportOptions>) {
+  const merged = { ...defaultExportOptions, ...options };
+
+  (field("exportSpans") as HTMLInputElement).checked = merged.spans;
+}
+
+function field(name: string) {
+  return form.elements.namedItem(name) as
+    | HTMLInputElement
+    | HTMLSelectElement;
+}
@@ -2,6 +2,16 @@ import {
   compressToEncodedURIComponent,
   decompressFromEncodedURIComponent,
 } from "lz-string";
+import { getFormatOptions, setFormatOptions } from "./formatOptions";
+import { getParseLimits, setParseLimits } from "./parseLimits";
+import {
+  getExportOptions,
+  getJsonOptions,
+  getPrinterOptions,
+  setExportOptions,
+  setJsonOptions,
+  setPrinterOptions,
+} from "./printerOptions";
 
 let copyButton;
 let copyButtonOriginalText;
@@ -45,6 +55,21 @@ function tryLoadFromShareLink(codeMirror) {
       "textarea.editor-input-text",
     )!;
     inputEditor.value = decoded["input"];
+    if (decoded["formatOptions"]) {
+      setFormatOptions(decoded["formatOptions"]);
+    }
+    if (decoded["parseLimits"]) {
+      setParseLimits(decoded["parseLimits"]);
+    }
+    if (decoded["printerOptions"]) {
+      setPrinterOptions(decoded["printerOptions"]);
+    }
+    if (decoded["jsonOptions"]) {
+      setJsonOptions(decoded["jsonOptions"]);
+    }
+    if (decoded["exportOptions"]) {
+      setExportOptions(decoded["exportOptions"]);
+    }
   }
 }
 
@@ -54,6 +79,11 @@ function shareData(codeMirror) {
     input: document.querySelector<HTMLTextAreaElement>(
       "textarea.editor-input-text",
     )!.value,
+    formatOptions: getFormatOptions(),
+    parseLimits: getParseLimits(),
+    printerOptions: getPrinterOptions(),
+    jsonOptions: getJsonOptions(),
+    exportOptions: getExportOptions(),
   };
 }
 